@@ -14,8 +14,6 @@ extern crate maidsafe_utilities;
 extern crate unwrap;
 
 use maidsafe_utilities::log as safe_log;
-use std::thread;
-use std::time::Duration;
 
 fn main() {
     unwrap!(safe_log::init(false));
@@ -28,8 +26,8 @@ fn main() {
 
     abc::log_msgs();
 
-    // Allow async loggers to function in the background thread
-    thread::sleep(Duration::from_millis(100));
+    // Wait for the async loggers to write everything before exiting
+    safe_log::shutdown();
 }
 
 mod abc {
@@ -85,6 +85,10 @@ pub mod log;
 mod log_or_panic;
 mod seeded_rng;
 /// Functions for serialisation and deserialisation
+///
+/// Everything here uses bincode, apart from [`serialise_as()`](serialisation/fn.serialise_as.html),
+/// [`deserialise_as()`](serialisation/fn.deserialise_as.html) and their `_with_limit` variants,
+/// which take the [`Format`](serialisation/trait.Format.html) to use.
 pub mod serialisation;
 /// Utilities related to threading.
 pub mod thread;
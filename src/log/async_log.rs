@@ -9,6 +9,7 @@
 
 // TODO: consider contributing this code to the log4rs crate.
 
+use super::rolling_file::{RollingFile, RollingPolicy};
 use crate::thread::{self, Joiner};
 use config_file_handler::FileHandler;
 use log::LogRecord;
@@ -30,12 +31,15 @@ use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::sync::mpsc::{self, Sender};
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex, Weak};
 
 /// Message terminator for streaming to Log Servers. Servers must look out for this sequence which
 /// demarcates the end of a particular log message.
 pub const MSG_TERMINATOR: [u8; 3] = [254, 253, 255];
 
+/// How many rotated files an `AsyncRollingFileAppender` keeps by default.
+const DEFAULT_ROLLING_FILE_KEEP: usize = 5;
+
 pub struct AsyncConsoleAppender;
 
 impl AsyncConsoleAppender {
@@ -127,6 +131,70 @@ impl AsyncFileAppenderBuilder {
     }
 }
 
+pub struct AsyncRollingFileAppender;
+
+impl AsyncRollingFileAppender {
+    pub fn builder<P: AsRef<Path>>(path: P) -> AsyncRollingFileAppenderBuilder {
+        AsyncRollingFileAppenderBuilder {
+            path: path.as_ref().to_path_buf(),
+            encoder: Box::new(PatternEncoder::default()),
+            append: true,
+            policy: RollingPolicy {
+                max_size: None,
+                daily: false,
+                keep: DEFAULT_ROLLING_FILE_KEEP,
+                compress: false,
+            },
+        }
+    }
+}
+
+pub struct AsyncRollingFileAppenderBuilder {
+    path: PathBuf,
+    encoder: Box<dyn Encode>,
+    append: bool,
+    policy: RollingPolicy,
+}
+
+impl AsyncRollingFileAppenderBuilder {
+    pub fn encoder(self, encoder: Box<dyn Encode>) -> Self {
+        AsyncRollingFileAppenderBuilder { encoder, ..self }
+    }
+
+    pub fn append(self, append: bool) -> Self {
+        AsyncRollingFileAppenderBuilder { append, ..self }
+    }
+
+    /// Rotates the file before a message would take it past `max_size` bytes.
+    pub fn max_size(mut self, max_size: u64) -> Self {
+        self.policy.max_size = Some(max_size);
+        self
+    }
+
+    /// Rotates the file on the first message of each day, in local time.
+    pub fn daily(mut self, daily: bool) -> Self {
+        self.policy.daily = daily;
+        self
+    }
+
+    /// Keeps the last `keep` rotated files, deleting older ones.
+    pub fn keep(mut self, keep: usize) -> Self {
+        self.policy.keep = keep;
+        self
+    }
+
+    /// Gzips rotated files.
+    pub fn compress(mut self, compress: bool) -> Self {
+        self.policy.compress = compress;
+        self
+    }
+
+    pub fn build(self) -> io::Result<AsyncAppender> {
+        let file = RollingFile::open(self.path, self.append, self.policy)?;
+        Ok(AsyncAppender::new(file, self.encoder))
+    }
+}
+
 pub struct AsyncServerAppender;
 
 impl AsyncServerAppender {
@@ -192,8 +260,13 @@ impl Deserialize for AsyncConsoleAppenderCreator {
     }
 }
 
-/// Takes an optional parameter for an output file name override.
-pub struct AsyncFileAppenderCreator(pub Option<String>);
+pub struct AsyncFileAppenderCreator {
+    /// Output file name which overrides the `output_file_name` in the config.
+    pub op_file_name_override: Option<String>,
+    /// Whether to append to the output file regardless of the config's `append` setting, so that
+    /// reloading the config doesn't truncate the log.
+    pub force_append: bool,
+}
 
 impl Deserialize for AsyncFileAppenderCreator {
     type Trait = dyn Append;
@@ -211,23 +284,7 @@ impl Deserialize for AsyncFileAppenderCreator {
             _ => return Err(Box::new(ConfigError("config must be a map".to_owned()))),
         };
 
-        let mut op_file = if let Some(op_file_name_override) = self.0.clone() {
-            op_file_name_override
-        } else {
-            match map.remove(&Value::String("output_file_name".to_owned())) {
-                Some(Value::String(op_file)) => op_file,
-                Some(_) => {
-                    return Err(Box::new(ConfigError(
-                        "`output_file_name` must be a string".to_owned(),
-                    )));
-                }
-                None => {
-                    return Err(Box::new(ConfigError(
-                        "`output_file_name` is required".to_owned(),
-                    )));
-                }
-            }
-        };
+        let mut op_file = output_file_name(&mut map, &self.op_file_name_override)?;
 
         let timestamp = match map.remove(&Value::String("file_timestamp".to_owned())) {
             Some(Value::Bool(t)) => t,
@@ -269,28 +326,87 @@ impl Deserialize for AsyncFileAppenderCreator {
             );
         }
 
-        let op_path = match FileHandler::<()>::new(&op_file, true) {
-            Ok(fh) => fh.path().to_path_buf(),
-            Err(e) => {
-                return Err(Box::new(ConfigError(format!(
-                    "Could not establish log file path: \
-                     {:?}",
-                    e
-                ))));
-            }
+        let op_path = output_path(&op_file)?;
+        let append = parse_append(&mut map, self.force_append)?;
+
+        let pattern = parse_pattern(&mut map, false)?;
+        let appender = AsyncFileAppender::builder(op_path)
+            .encoder(pattern)
+            .append(append)
+            .timestamp(timestamp)
+            .build()?;
+
+        Ok(Box::new(appender))
+    }
+}
+
+pub struct AsyncRollingFileAppenderCreator {
+    /// Output file name which overrides the `output_file_name` in the config.
+    pub op_file_name_override: Option<String>,
+    /// Whether to append to the output file regardless of the config's `append` setting.
+    pub force_append: bool,
+}
+
+impl Deserialize for AsyncRollingFileAppenderCreator {
+    type Trait = dyn Append;
+    type Config = Value;
+
+    fn deserialize(
+        &self,
+        config: Value,
+        _deserializers: &Deserializers,
+    ) -> Result<Box<dyn Append>, Box<dyn Error + Sync + Send>> {
+        let mut map = match config {
+            Value::Map(map) => map,
+            _ => return Err(Box::new(ConfigError("config must be a map".to_owned()))),
         };
 
-        let append = match map.remove(&Value::String("append".to_owned())) {
-            Some(Value::Bool(append)) => append,
-            Some(_) => return Err(Box::new(ConfigError("`append` must be a bool".to_owned()))),
+        let op_file = output_file_name(&mut map, &self.op_file_name_override)?;
+        let op_path = output_path(&op_file)?;
+        let append = parse_append(&mut map, self.force_append)?;
+
+        let mut builder = AsyncRollingFileAppender::builder(op_path).append(append);
+        let max_size = match map.remove(&Value::String("max_size".to_owned())) {
+            Some(value) => Some(parse_size(value)?),
+            None => None,
+        };
+        if let Some(max_size) = max_size {
+            builder = builder.max_size(max_size);
+        }
+        let daily = match map.remove(&Value::String("daily".to_owned())) {
+            Some(Value::Bool(daily)) => daily,
+            Some(_) => return Err(Box::new(ConfigError("`daily` must be a bool".to_owned()))),
+            None => false,
+        };
+        if max_size.is_none() && !daily {
+            return Err(Box::new(ConfigError(
+                "at least one of `max_size` and `daily` is required".to_owned(),
+            )));
+        }
+        match map.remove(&Value::String("keep".to_owned())) {
+            Some(Value::I64(keep)) if keep >= 0 => builder = builder.keep(keep as usize),
+            Some(_) => {
+                return Err(Box::new(ConfigError(
+                    "`keep` must be a non-negative integer".to_owned(),
+                )));
+            }
+            None => (),
+        }
+        let compress = match map.remove(&Value::String("compress".to_owned())) {
+            Some(Value::Bool(compress)) => compress,
+            Some(_) => {
+                return Err(Box::new(ConfigError(
+                    "`compress` must be a bool".to_owned(),
+                )));
+            }
             None => false,
         };
 
         let pattern = parse_pattern(&mut map, false)?;
-        let appender = AsyncFileAppender::builder(op_path)
+        let appender = builder
             .encoder(pattern)
-            .append(append)
-            .timestamp(timestamp)
+            .daily(daily)
+            .compress(compress)
             .build()?;
 
         Ok(Box::new(appender))
@@ -346,6 +462,81 @@ impl Deserialize for AsyncServerAppenderCreator {
     }
 }
 
+fn output_file_name(
+    map: &mut BTreeMap<Value, Value>,
+    op_file_name_override: &Option<String>,
+) -> Result<String, Box<dyn Error + Sync + Send>> {
+    if let Some(ref op_file_name_override) = *op_file_name_override {
+        return Ok(op_file_name_override.clone());
+    }
+    match map.remove(&Value::String("output_file_name".to_owned())) {
+        Some(Value::String(op_file)) => Ok(op_file),
+        Some(_) => Err(Box::new(ConfigError(
+            "`output_file_name` must be a string".to_owned(),
+        ))),
+        None => Err(Box::new(ConfigError(
+            "`output_file_name` is required".to_owned(),
+        ))),
+    }
+}
+
+fn output_path(op_file: &str) -> Result<PathBuf, Box<dyn Error + Sync + Send>> {
+    match FileHandler::<()>::new(op_file, true) {
+        Ok(fh) => Ok(fh.path().to_path_buf()),
+        Err(e) => Err(Box::new(ConfigError(format!(
+            "Could not establish log file path: \
+             {:?}",
+            e
+        )))),
+    }
+}
+
+fn parse_append(
+    map: &mut BTreeMap<Value, Value>,
+    force_append: bool,
+) -> Result<bool, Box<dyn Error + Sync + Send>> {
+    match map.remove(&Value::String("append".to_owned())) {
+        Some(Value::Bool(append)) => Ok(append || force_append),
+        Some(_) => Err(Box::new(ConfigError("`append` must be a bool".to_owned()))),
+        None => Ok(force_append),
+    }
+}
+
+// Parses a size in bytes, given either as an integer or as a string with an optional `B`, `KB`, `MB`
+// or `GB` suffix (in multiples of 1024), e.g. `"10 MB"`.
+fn parse_size(value: Value) -> Result<u64, Box<dyn Error + Sync + Send>> {
+    let error = || ConfigError("`max_size` must be a positive size, e.g. \"10 MB\"".to_owned());
+    let size = match value {
+        Value::I64(size) if size > 0 => size as u64,
+        Value::String(size) => {
+            let size = size.trim().to_uppercase();
+            let (digits, multiplier) = match size.find(|c: char| !c.is_ascii_digit()) {
+                Some(index) => {
+                    let multiplier = match size[index..].trim() {
+                        "B" => 1,
+                        "KB" => 1 << 10,
+                        "MB" => 1 << 20,
+                        "GB" => 1 << 30,
+                        _ => return Err(Box::new(error())),
+                    };
+                    (&size[..index], multiplier)
+                }
+                None => (&size[..], 1),
+            };
+            digits
+                .parse::<u64>()
+                .ok()
+                .and_then(|size| size.checked_mul(multiplier))
+                .ok_or_else(|| Box::new(error()))?
+        }
+        _ => return Err(Box::new(error())),
+    };
+    if size == 0 {
+        return Err(Box::new(error()));
+    }
+    Ok(size)
+}
+
 fn parse_pattern(
     map: &mut BTreeMap<Value, Value>,
     is_websocket: bool,
@@ -380,16 +571,80 @@ impl Display for ConfigError {
     }
 }
 
+lazy_static! {
+    static ref ASYNC_APPENDERS: Mutex<Vec<Weak<AsyncChannel>>> = Mutex::new(Vec::new());
+}
+
+/// Blocks until every record queued by a live `AsyncAppender` has been written.
+pub fn flush_all() {
+    let acks = live_channels()
+        .iter()
+        .filter_map(|channel| {
+            let (ack_tx, ack_rx) = mpsc::channel();
+            if channel.send(AsyncEvent::Flush(ack_tx)) {
+                Some(ack_rx)
+            } else {
+                None
+            }
+        })
+        .collect::<Vec<_>>();
+    // An error means the thread has exited, having written everything it could.
+    for ack_rx in acks {
+        let _ = ack_rx.recv();
+    }
+}
+
+/// Flushes every live `AsyncAppender`, then stops their threads, closing their files and
+/// connections.  Records appended afterwards are discarded.
+pub fn shutdown_all() {
+    flush_all();
+    for channel in live_channels() {
+        channel.close();
+    }
+}
+
+fn live_channels() -> Vec<Arc<AsyncChannel>> {
+    let mut appenders = unwrap!(ASYNC_APPENDERS.lock());
+    appenders.retain(|channel| channel.upgrade().is_some());
+    appenders.iter().filter_map(Weak::upgrade).collect()
+}
+
 enum AsyncEvent {
     Log(Vec<u8>),
+    Flush(Sender<()>),
     Terminate,
 }
 
+// The sending end of an `AsyncLog` thread, shared with `ASYNC_APPENDERS` so that the thread can be
+// flushed and stopped independently of the appender's lifetime.
+#[derive(Debug)]
+struct AsyncChannel {
+    tx: Mutex<Option<Sender<AsyncEvent>>>,
+    joiner: Mutex<Option<Joiner>>,
+}
+
+impl AsyncChannel {
+    // Returns `false` if the thread has been stopped.
+    fn send(&self, event: AsyncEvent) -> bool {
+        match *unwrap!(self.tx.lock()) {
+            Some(ref tx) => tx.send(event).is_ok(),
+            None => false,
+        }
+    }
+
+    fn close(&self) {
+        if let Some(tx) = unwrap!(self.tx.lock()).take() {
+            let _ = tx.send(AsyncEvent::Terminate);
+        }
+        // Dropping the joiner waits for the thread to exit.
+        let _ = unwrap!(self.joiner.lock()).take();
+    }
+}
+
 #[derive(Debug)]
 pub struct AsyncAppender {
     encoder: Box<dyn Encode>,
-    tx: Mutex<Sender<AsyncEvent>>,
-    _raii_joiner: Joiner,
+    channel: Arc<AsyncChannel>,
 }
 
 impl AsyncAppender {
@@ -414,16 +669,21 @@ impl AsyncAppender {
                             let _ = writer.sync_write(&msg);
                         }
                     }
+                    AsyncEvent::Flush(ack_tx) => {
+                        let _ = ack_tx.send(());
+                    }
                     AsyncEvent::Terminate => break,
                 }
             }
         });
 
-        AsyncAppender {
-            encoder,
-            tx: Mutex::new(tx),
-            _raii_joiner: joiner,
-        }
+        let channel = Arc::new(AsyncChannel {
+            tx: Mutex::new(Some(tx)),
+            joiner: Mutex::new(Some(joiner)),
+        });
+        unwrap!(ASYNC_APPENDERS.lock()).push(Arc::downgrade(&channel));
+
+        AsyncAppender { encoder, channel }
     }
 }
 
@@ -431,14 +691,14 @@ impl Append for AsyncAppender {
     fn append(&self, record: &LogRecord) -> Result<(), Box<dyn Error + Sync + Send>> {
         let mut msg = Vec::new();
         self.encoder.encode(&mut SimpleWriter(&mut msg), record)?;
-        unwrap!(self.tx.lock()).send(AsyncEvent::Log(msg))?;
+        let _ = self.channel.send(AsyncEvent::Log(msg));
         Ok(())
     }
 }
 
 impl Drop for AsyncAppender {
     fn drop(&mut self) {
-        let _ = unwrap!(self.tx.lock()).send(AsyncEvent::Terminate);
+        self.channel.close();
     }
 }
 
@@ -461,9 +721,40 @@ impl SyncWrite for File {
     }
 }
 
+impl SyncWrite for RollingFile {
+    fn sync_write(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.write(buf)
+    }
+}
+
 impl SyncWrite for TcpStream {
     fn sync_write(&mut self, buf: &[u8]) -> io::Result<()> {
         self.write_all(buf)?;
         self.write_all(&MSG_TERMINATOR[..])
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_sizes() {
+        let size = |value| unwrap!(parse_size(value));
+        assert_eq!(size(Value::I64(1000)), 1000);
+        assert_eq!(size(Value::String("1000".to_owned())), 1000);
+        assert_eq!(size(Value::String("512B".to_owned())), 512);
+        assert_eq!(size(Value::String("64 kb".to_owned())), 64 * 1024);
+        assert_eq!(size(Value::String(" 10 MB ".to_owned())), 10 * 1024 * 1024);
+        assert_eq!(
+            size(Value::String("2GB".to_owned())),
+            2 * 1024 * 1024 * 1024
+        );
+
+        for invalid in &["", "0", "MB", "10 TB", "1.5 MB", "-1"] {
+            assert!(parse_size(Value::String((*invalid).to_owned())).is_err());
+        }
+        assert!(parse_size(Value::I64(0)).is_err());
+        assert!(parse_size(Value::Bool(true)).is_err());
+    }
+}
@@ -0,0 +1,223 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// https://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+use log4rs::config::Config;
+use log4rs::file::{Deserializers, RawConfig};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::time::Duration;
+
+/// How often the config file is checked for changes if it doesn't specify a `refresh_rate`.
+pub const DEFAULT_REFRESH_RATE: Duration = Duration::from_secs(10);
+
+/// A config parsed from `log.toml`.
+pub struct LoadedConfig {
+    pub config: Config,
+    /// Appenders and loggers which couldn't be created, and so are missing from `config`.
+    pub errors: Vec<String>,
+    pub refresh_rate: Option<Duration>,
+}
+
+pub fn load_config(source: &str, deserializers: &Deserializers) -> Result<LoadedConfig, String> {
+    let raw_config = toml::from_str::<RawConfig>(source).map_err(|e| format!("{}", e))?;
+    let (appenders, appender_errors) = raw_config.appenders_lossy(deserializers);
+    let (config, config_errors) = Config::builder()
+        .appenders(appenders)
+        .loggers(raw_config.loggers())
+        .build_lossy(raw_config.root());
+
+    let errors = appender_errors
+        .iter()
+        .map(|e| format!("{}", e))
+        .chain(config_errors.iter().map(|e| format!("{}", e)))
+        .collect();
+    Ok(LoadedConfig {
+        config,
+        errors,
+        refresh_rate: raw_config.refresh_rate(),
+    })
+}
+
+/// Polls the config file for changes, passing each new config which loads without errors to
+/// `apply`.  Watching stops once `apply` returns `false`.
+pub struct ConfigWatcher<F> {
+    path: PathBuf,
+    source: String,
+    deserializers: Deserializers,
+    refresh_rate: Duration,
+    apply: F,
+    stopped: bool,
+}
+
+impl<F: Fn(Config) -> bool> ConfigWatcher<F> {
+    /// `source` is the contents of the file when the current config was loaded from it.
+    pub fn new(
+        path: PathBuf,
+        source: String,
+        deserializers: Deserializers,
+        refresh_rate: Option<Duration>,
+        apply: F,
+    ) -> Self {
+        ConfigWatcher {
+            path,
+            source,
+            deserializers,
+            refresh_rate: refresh_rate.unwrap_or(DEFAULT_REFRESH_RATE),
+            apply,
+            stopped: false,
+        }
+    }
+
+    /// Checks the file once, returning `Ok(true)` if a changed config was applied.  If the changed
+    /// config can't be loaded, the current one is kept and the file isn't retried until it changes
+    /// again.
+    pub fn check(&mut self) -> Result<bool, String> {
+        if self.stopped {
+            return Ok(false);
+        }
+        let source = fs::read_to_string(&self.path).map_err(|e| format!("{}", e))?;
+        if source == self.source {
+            return Ok(false);
+        }
+        self.source = source;
+
+        let loaded = load_config(&self.source, &self.deserializers)?;
+        if !loaded.errors.is_empty() {
+            return Err(loaded.errors.join("; "));
+        }
+        if !(self.apply)(loaded.config) {
+            self.stopped = true;
+            return Ok(false);
+        }
+        if let Some(refresh_rate) = loaded.refresh_rate {
+            self.refresh_rate = refresh_rate;
+        }
+        Ok(true)
+    }
+
+    /// Checks the file every `refresh_rate` until watching is stopped, or until `stop` receives a
+    /// message or its sender is dropped.
+    pub fn run(mut self, stop: Receiver<()>) {
+        while !self.stopped {
+            match stop.recv_timeout(self.refresh_rate) {
+                Err(RecvTimeoutError::Timeout) => (),
+                Ok(()) | Err(RecvTimeoutError::Disconnected) => return,
+            }
+            match self.check() {
+                Ok(true) => info!("Reloaded log config from {}", self.path.display()),
+                Ok(false) => (),
+                Err(error) => warn!(
+                    "Failed to reload log config from {}, keeping the previous config: {}",
+                    self.path.display(),
+                    error
+                ),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::log::async_log::AsyncConsoleAppenderCreator;
+    use log::LogLevelFilter;
+    use std::sync::mpsc;
+    use std::time::Instant;
+    use std::{env, process, thread};
+
+    const CONFIG: &str = r#"
+        [appenders.console]
+        kind = "async_console"
+
+        [root]
+        level = "error"
+        appenders = ["console"]
+    "#;
+
+    #[test]
+    fn reload() {
+        let path = env::temp_dir().join(format!("maidsafe_utilities_log_{}.toml", process::id()));
+        unwrap!(fs::write(&path, CONFIG));
+        let mut deserializers = Deserializers::default();
+        deserializers.insert("async_console", AsyncConsoleAppenderCreator);
+        let loaded = unwrap!(load_config(CONFIG, &deserializers));
+        assert!(loaded.errors.is_empty());
+        assert_eq!(loaded.config.root().level(), LogLevelFilter::Error);
+
+        let (tx, rx) = mpsc::channel();
+        let mut watcher = ConfigWatcher::new(
+            path.clone(),
+            CONFIG.to_owned(),
+            deserializers,
+            loaded.refresh_rate,
+            move |config| {
+                let superseded = config.root().level() == LogLevelFilter::Off;
+                unwrap!(tx.send(config));
+                !superseded
+            },
+        );
+        assert!(!unwrap!(watcher.check()));
+        assert!(rx.try_recv().is_err());
+
+        // Levels and loggers are applied.
+        let changed = format!(
+            "refresh_rate = \"1 second\"\n{}\n[loggers.\"crust::peer\"]\nlevel = \"trace\"\n",
+            CONFIG.replace("\"error\"", "\"debug\"")
+        );
+        unwrap!(fs::write(&path, &changed));
+        assert!(unwrap!(watcher.check()));
+        let config = unwrap!(rx.try_recv());
+        assert_eq!(config.root().level(), LogLevelFilter::Debug);
+        assert_eq!(config.loggers()[0].name(), "crust::peer");
+        assert_eq!(config.loggers()[0].level(), LogLevelFilter::Trace);
+        assert_eq!(watcher.refresh_rate, Duration::from_secs(1));
+
+        // Unparseable configs, and ones with appenders which can't be created, are rejected.
+        unwrap!(fs::write(&path, "[root\nlevel = \"info\""));
+        let _ = unwrap_err!(watcher.check());
+        assert!(!unwrap!(watcher.check()));
+        unwrap!(fs::write(
+            &path,
+            CONFIG.replace("async_console", "async_unknown")
+        ));
+        let _ = unwrap_err!(watcher.check());
+        assert!(rx.try_recv().is_err());
+
+        unwrap!(fs::write(&path, CONFIG));
+        assert!(unwrap!(watcher.check()));
+        assert_eq!(unwrap!(rx.try_recv()).root().level(), LogLevelFilter::Error);
+
+        // Once `apply` returns `false`, the file is no longer checked.
+        unwrap!(fs::write(&path, CONFIG.replace("\"error\"", "\"off\"")));
+        assert!(!unwrap!(watcher.check()));
+        let _ = unwrap!(rx.try_recv());
+        unwrap!(fs::write(&path, CONFIG));
+        assert!(!unwrap!(watcher.check()));
+        assert!(rx.try_recv().is_err());
+        unwrap!(fs::remove_file(&path));
+    }
+
+    #[test]
+    fn stop() {
+        let (tx, rx) = mpsc::channel();
+        let watcher = ConfigWatcher::new(
+            env::temp_dir().join("maidsafe_utilities_log_missing.toml"),
+            CONFIG.to_owned(),
+            Deserializers::default(),
+            None,
+            |_| true,
+        );
+        let start = Instant::now();
+        let joiner = thread::spawn(move || watcher.run(rx));
+        drop(tx);
+        unwrap!(joiner.join());
+        assert!(start.elapsed() < DEFAULT_REFRESH_RATE);
+    }
+}
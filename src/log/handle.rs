@@ -0,0 +1,303 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// https://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+use super::parse_level_spec;
+use log::{LogLevelFilter, LogRecord};
+use log4rs::append::Append;
+use log4rs::config::{Appender, Config, Logger, Root};
+use log4rs::filter::Response;
+use log4rs::Handle;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::{self, Debug, Formatter};
+use std::sync::{Arc, Mutex};
+
+lazy_static! {
+    static ref LOG_HANDLE: Mutex<Option<LogHandle>> = Mutex::new(None);
+}
+
+/// Returns a handle for changing log levels at runtime, or `None` if logging hasn't been
+/// initialised by one of this module's `init` functions.
+pub fn handle() -> Option<LogHandle> {
+    unwrap!(LOG_HANDLE.lock()).clone()
+}
+
+/// Handle to the active logger, allowing its levels to be changed at runtime.
+///
+/// Levels set through the handle override those given by `RUST_LOG` or `log.toml` until
+/// [`reset()`](#method.reset) is called, `log.toml` is reloaded or the logger is reinitialised.
+/// The handle can be cloned and shared between threads.
+#[derive(Clone)]
+pub struct LogHandle {
+    state: Arc<Mutex<LogState>>,
+}
+
+struct LogState {
+    handle: Handle,
+    base: Arc<Config>,
+    // Incremented each time the config is replaced other than by reloading `log.toml`.
+    generation: u64,
+    default_level: Option<LogLevelFilter>,
+    module_levels: BTreeMap<String, LogLevelFilter>,
+}
+
+impl LogHandle {
+    /// Sets the level of the root logger, used by modules without a level of their own.
+    pub fn set_default_level(&self, level: LogLevelFilter) {
+        self.update(|state| state.default_level = Some(level));
+    }
+
+    /// Sets the level of `module` and its submodules, e.g. `"routing::core"`.
+    pub fn set_module_level(&self, module: &str, level: LogLevelFilter) {
+        self.update(|state| {
+            let _ = state.module_levels.insert(module.to_owned(), level);
+        });
+    }
+
+    /// Sets levels from a specification in the same format as the `RUST_LOG` environment
+    /// variable, e.g. `"info,routing=debug,crust"`.
+    ///
+    /// Levels not mentioned in `spec` are left unchanged, and modules named without a level are set
+    /// to the default level.
+    pub fn set_levels(&self, spec: &str) -> Result<(), String> {
+        let mut state = unwrap!(self.state.lock());
+        let current_default = state
+            .default_level
+            .unwrap_or_else(|| state.base.root().level());
+        let (default_level, loggers) =
+            parse_level_spec(spec, current_default).map_err(|e| format!("{}", e))?;
+        if default_level.is_some() {
+            state.default_level = default_level;
+        }
+        for logger in loggers {
+            let _ = state
+                .module_levels
+                .insert(logger.name().to_owned(), logger.level());
+        }
+        state.apply();
+        Ok(())
+    }
+
+    /// Discards all levels set through the handle, restoring those the logger was initialised
+    /// with.
+    pub fn reset(&self) {
+        self.update(|state| {
+            state.default_level = None;
+            state.module_levels.clear();
+        });
+    }
+
+    fn update<F: FnOnce(&mut LogState)>(&self, f: F) {
+        let mut state = unwrap!(self.state.lock());
+        f(&mut state);
+        state.apply();
+    }
+}
+
+impl LogState {
+    fn apply(&self) {
+        self.handle.set_config(live_config(
+            &self.base,
+            self.default_level,
+            &self.module_levels,
+        ));
+    }
+}
+
+/// Initialises the global logger with `config`, making it available through
+/// [`handle()`](fn.handle.html).  If it has already been initialised, its config is replaced
+/// instead.
+///
+/// Returns the handle and the generation of the installed config.
+pub fn install(config: Config) -> Result<(LogHandle, u64), String> {
+    let mut current = unwrap!(LOG_HANDLE.lock());
+    if let Some(ref log_handle) = *current {
+        let generation = replace_config(log_handle, config);
+        return Ok((log_handle.clone(), generation));
+    }
+
+    let base = Arc::new(config);
+    let handle = log4rs::init_config(live_config(&base, None, &BTreeMap::new()))
+        .map_err(|e| format!("{}", e))?;
+    let log_handle = LogHandle {
+        state: Arc::new(Mutex::new(LogState {
+            handle,
+            base,
+            generation: 0,
+            default_level: None,
+            module_levels: BTreeMap::new(),
+        })),
+    };
+    *current = Some(log_handle.clone());
+    Ok((log_handle, 0))
+}
+
+/// Replaces the logger's config, discarding any levels set through the handle.  Returns the
+/// generation of the new config.
+pub fn replace_config(log_handle: &LogHandle, config: Config) -> u64 {
+    let mut generation = 0;
+    log_handle.update(|state| {
+        state.generation += 1;
+        generation = state.generation;
+        set_base(state, config);
+    });
+    generation
+}
+
+/// Replaces the logger's config with a reloaded version of the config of the given generation.
+/// Returns `false` without changing anything if a different config has been installed since.
+pub fn reload_config(log_handle: &LogHandle, generation: u64, config: Config) -> bool {
+    let mut reloaded = false;
+    log_handle.update(|state| {
+        if state.generation == generation {
+            set_base(state, config);
+            reloaded = true;
+        }
+    });
+    reloaded
+}
+
+fn set_base(state: &mut LogState, config: Config) {
+    state.base = Arc::new(config);
+    state.default_level = None;
+    state.module_levels.clear();
+}
+
+// Builds a config which shares the appenders of `base`, with levels overridden as given.
+fn live_config(
+    base: &Arc<Config>,
+    default_level: Option<LogLevelFilter>,
+    module_levels: &BTreeMap<String, LogLevelFilter>,
+) -> Config {
+    let appenders = base
+        .appenders()
+        .iter()
+        .enumerate()
+        .map(|(index, appender)| {
+            let shared = SharedAppender {
+                config: Arc::clone(base),
+                index,
+            };
+            Appender::builder().build(appender.name().to_owned(), Box::new(shared))
+        })
+        .collect::<Vec<_>>();
+
+    let mut loggers = base
+        .loggers()
+        .iter()
+        .map(|logger| {
+            let level = module_levels
+                .get(logger.name())
+                .cloned()
+                .unwrap_or_else(|| logger.level());
+            Logger::builder()
+                .appenders(logger.appenders().iter().cloned())
+                .additive(logger.additive())
+                .build(logger.name().to_owned(), level)
+        })
+        .collect::<Vec<_>>();
+    for (module, level) in module_levels {
+        if !base.loggers().iter().any(|logger| logger.name() == module) {
+            loggers.push(Logger::builder().build(module.clone(), *level));
+        }
+    }
+
+    let root = Root::builder()
+        .appenders(base.root().appenders().iter().cloned())
+        .build(default_level.unwrap_or_else(|| base.root().level()));
+    let (config, _errors) = Config::builder()
+        .appenders(appenders)
+        .loggers(loggers)
+        .build_lossy(root);
+    config
+}
+
+// One of the appenders of a config, applying its filters.  This allows the appenders to outlive
+// the config they were created for, so that levels can be changed without recreating them.
+struct SharedAppender {
+    config: Arc<Config>,
+    index: usize,
+}
+
+impl Debug for SharedAppender {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        Debug::fmt(self.config.appenders()[self.index].appender(), formatter)
+    }
+}
+
+impl Append for SharedAppender {
+    fn append(&self, record: &LogRecord) -> Result<(), Box<dyn Error + Sync + Send>> {
+        let appender = &self.config.appenders()[self.index];
+        for filter in appender.filters() {
+            match filter.filter(record) {
+                Response::Accept => break,
+                Response::Neutral => (),
+                Response::Reject => return Ok(()),
+            }
+        }
+        appender.appender().append(record)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug)]
+    struct NullAppender;
+
+    impl Append for NullAppender {
+        fn append(&self, _record: &LogRecord) -> Result<(), Box<dyn Error + Sync + Send>> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn level_overrides() {
+        let base = unwrap!(Config::builder()
+            .appender(Appender::builder().build("null".to_owned(), Box::new(NullAppender)))
+            .logger(
+                Logger::builder()
+                    .appender("null".to_owned())
+                    .additive(false)
+                    .build("routing".to_owned(), LogLevelFilter::Info)
+            )
+            .build(
+                Root::builder()
+                    .appender("null".to_owned())
+                    .build(LogLevelFilter::Warn)
+            ));
+        let base = Arc::new(base);
+
+        let config = live_config(&base, None, &BTreeMap::new());
+        assert_eq!(config.appenders()[0].name(), "null");
+        assert_eq!(config.root().level(), LogLevelFilter::Warn);
+        assert_eq!(config.loggers().len(), 1);
+
+        let mut module_levels = BTreeMap::new();
+        let _ = module_levels.insert("routing".to_owned(), LogLevelFilter::Trace);
+        let _ = module_levels.insert("crust::peer".to_owned(), LogLevelFilter::Debug);
+        let config = live_config(&base, Some(LogLevelFilter::Error), &module_levels);
+        assert_eq!(config.root().level(), LogLevelFilter::Error);
+        assert_eq!(config.root().appenders(), ["null"]);
+        let loggers = config
+            .loggers()
+            .iter()
+            .map(|logger| (logger.name(), logger.level(), logger.additive()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            loggers,
+            vec![
+                ("routing", LogLevelFilter::Trace, false),
+                ("crust::peer", LogLevelFilter::Debug, true),
+            ]
+        );
+        assert_eq!(config.loggers()[0].appenders(), ["null"]);
+    }
+}
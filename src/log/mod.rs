@@ -33,6 +33,10 @@
 //!
 //! The functions can safely be called multiple times concurrently.
 //!
+//! Messages are written by background threads, so may not have reached their destination when the
+//! logging macro returns.  Call [`flush()`](fn.flush.html) to wait for them to be written, and
+//! [`shutdown()`](fn.shutdown.html) before the process exits.
+//!
 //! #Examples
 //!
 //! ```
@@ -66,6 +70,32 @@
 //! }
 //! ```
 //!
+//! When logging is configured by `log.toml`, the file is checked for changes every `refresh_rate`
+//! (or every 10 seconds if it doesn't specify one) and any changes to levels, loggers and appenders
+//! are applied without restarting.  If the changed file can't be parsed, or any of its appenders
+//! can't be created, a warning is logged and the previous configuration is kept.  Reloaded
+//! `async_file` and `async_rolling_file` appenders always append to their output file rather than
+//! truncating it.
+//!
+//! An `async_rolling_file` appender writes to `output_file_name` like `async_file`, but rotates it
+//! to `<output_file_name>.1` once it would exceed `max_size` (e.g. `"10 MB"`) and/or, if `daily` is
+//! `true`, at the first message of each day.  Older files are renamed to `.2`, `.3` and so on, up to
+//! `keep` (5 by default), and are gzipped with a `.gz` extension if `compress` is `true`:
+//!
+//! ```toml
+//! [appenders.rolling]
+//! kind = "async_rolling_file"
+//! output_file_name = "node.log"
+//! max_size = "10 MB"
+//! daily = true
+//! keep = 7
+//! compress = true
+//! ```
+//!
+//! Levels can also be changed at runtime through the [`LogHandle`](struct.LogHandle.html) returned by
+//! [`handle()`](fn.handle.html) once logging has been initialised, e.g. in response to an admin
+//! command.  It accepts the same syntax as `RUST_LOG`, described below.
+//!
 //! Environment variable `RUST_LOG` can be set and fine-tuned to get various modules logging to
 //! different levels. E.g. `RUST_LOG=mod0,mod1=debug,mod2,mod3` will have `mod0` & `mod1` logging at
 //! `Debug` and more severe levels while `mod2` & `mod3` logging at default (currently `Warn`) and
@@ -74,13 +104,20 @@
 //! severe ones.
 
 pub use self::async_log::MSG_TERMINATOR;
+pub use self::handle::{handle, LogHandle};
 
 mod async_log;
+mod config_watcher;
+mod handle;
+mod rolling_file;
 
 use self::async_log::{
     AsyncConsoleAppender, AsyncConsoleAppenderCreator, AsyncFileAppender, AsyncFileAppenderCreator,
-    AsyncServerAppender, AsyncServerAppenderCreator,
+    AsyncRollingFileAppenderCreator, AsyncServerAppender, AsyncServerAppenderCreator,
 };
+use self::config_watcher::{load_config, ConfigWatcher};
+use self::handle::{install, reload_config};
+use crate::thread;
 
 use config_file_handler::FileHandler;
 use log::LogLevelFilter;
@@ -91,14 +128,21 @@ use log4rs::file::Deserializers;
 use std::borrow::Borrow;
 use std::env;
 use std::fmt::{self, Display, Formatter};
+use std::fs;
 use std::net::ToSocketAddrs;
 use std::path::Path;
-use std::sync::Once;
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Mutex, Once};
 
 static INITIALISE_LOGGER: Once = Once::new();
 static CONFIG_FILE: &str = "log.toml";
 static DEFAULT_LOG_LEVEL_FILTER: LogLevelFilter = LogLevelFilter::Warn;
 
+lazy_static! {
+    // The thread watching `log.toml` for changes, which stops when the sender is dropped.
+    static ref CONFIG_WATCHER: Mutex<Option<(Sender<()>, thread::Joiner)>> = Mutex::new(None);
+}
+
 /// Initialises the `env_logger` for output to stdout.
 ///
 /// For further details, see the [module docs](index.html).
@@ -120,21 +164,66 @@ where
     init_once_guard(|| init_impl(show_thread_name, Some(output_file_name_override.into())))
 }
 
+/// Like [`init()`](fn.init.html), but if logging has already been initialised, replaces its
+/// configuration rather than failing.
+///
+/// The replaced appenders are flushed and closed, and levels set through the
+/// [`LogHandle`](struct.LogHandle.html) are discarded.  This is intended for test suites which need
+/// a different logging setup per test; once it has been called, the one-shot `init` functions fail.
+pub fn reinit(show_thread_name: bool) -> Result<(), String> {
+    reinit_guard(|| init_impl(show_thread_name, None))
+}
+
+/// Like [`init_with_output_file()`](fn.init_with_output_file.html), but if logging has already
+/// been initialised, replaces its configuration rather than failing.
+///
+/// See [`reinit()`](fn.reinit.html) for details.
+pub fn reinit_with_output_file<S>(
+    show_thread_name: bool,
+    output_file_name_override: S,
+) -> Result<(), String>
+where
+    S: Into<String>,
+{
+    reinit_guard(|| init_impl(show_thread_name, Some(output_file_name_override.into())))
+}
+
 fn init_impl(show_thread_name: bool, op_file_name_override: Option<String>) -> Result<(), String> {
     let log_config_path = FileHandler::<()>::open(CONFIG_FILE, false)
         .ok()
         .map(|fh| fh.path().to_path_buf());
 
     if let Some(config_path) = log_config_path {
-        let mut deserializers = Deserializers::default();
-        deserializers.insert("async_console", AsyncConsoleAppenderCreator);
-        deserializers.insert(
-            "async_file",
-            AsyncFileAppenderCreator(op_file_name_override),
-        );
-        deserializers.insert("async_server", AsyncServerAppenderCreator);
+        let source = fs::read_to_string(&config_path).map_err(|e| format!("{}", e))?;
+        let loaded = load_config(
+            &source,
+            &make_deserializers(op_file_name_override.clone(), false),
+        )?;
+        let (handle, generation) = install(loaded.config)?;
+        // The parts of the config which could be set up are installed, so the rest can be
+        // reported through them.
+        for error in &loaded.errors {
+            warn!(
+                "Ignoring part of the log config in {}: {}",
+                config_path.display(),
+                error
+            );
+        }
 
-        log4rs::init_file(config_path, deserializers).map_err(|e| format!("{}", e))
+        // The watcher also stops by itself once the logger is reinitialised with a different
+        // config.
+        let watcher = ConfigWatcher::new(
+            config_path,
+            source,
+            make_deserializers(op_file_name_override, true),
+            loaded.refresh_rate,
+            move |config| reload_config(&handle, generation, config),
+        );
+        let (stop_tx, stop_rx) = mpsc::channel();
+        let joiner = thread::named("LogConfigWatcher", move || watcher.run(stop_rx));
+        let previous = unwrap!(CONFIG_WATCHER.lock()).replace((stop_tx, joiner));
+        drop(previous);
+        Ok(())
     } else {
         let console_appender = AsyncConsoleAppender::builder()
             .encoder(Box::new(make_pattern(show_thread_name)))
@@ -156,9 +245,7 @@ fn init_impl(show_thread_name: bool, op_file_name_override: Option<String>) -> R
             .build(root)
             .map_err(|e| format!("{}", e))?;
 
-        log4rs::init_config(config)
-            .map_err(|e| format!("{}", e))
-            .map(|_| ())
+        install(config).map(|_| ())
     }
 }
 
@@ -170,65 +257,60 @@ pub fn init_to_file<P: AsRef<Path>>(
     file_path: P,
     log_to_console: bool,
 ) -> Result<(), String> {
-    let mut result = Err("Logger already initialised".to_owned());
+    init_once_guard(|| init_to_file_impl(show_thread_name, file_path, log_to_console))
+}
 
-    INITIALISE_LOGGER.call_once(|| {
-        let (default_level, loggers) = match parse_loggers_from_env() {
-            Ok((level, loggers)) => (level, loggers),
-            Err(error) => {
-                result = Err(format!("{}", error));
-                return;
-            }
-        };
+/// Like [`init_to_file()`](fn.init_to_file.html), but if logging has already been initialised,
+/// replaces its configuration rather than failing.
+///
+/// See [`reinit()`](fn.reinit.html) for details.
+pub fn reinit_to_file<P: AsRef<Path>>(
+    show_thread_name: bool,
+    file_path: P,
+    log_to_console: bool,
+) -> Result<(), String> {
+    reinit_guard(|| init_to_file_impl(show_thread_name, file_path, log_to_console))
+}
 
-        let mut root = Root::builder().appender("file".to_owned());
+fn init_to_file_impl<P: AsRef<Path>>(
+    show_thread_name: bool,
+    file_path: P,
+    log_to_console: bool,
+) -> Result<(), String> {
+    let (default_level, loggers) = parse_loggers_from_env().map_err(|e| format!("{}", e))?;
 
-        if log_to_console {
-            root = root.appender("console".to_owned());
-        }
+    let mut root = Root::builder().appender("file".to_owned());
 
-        let root = root.build(default_level);
+    if log_to_console {
+        root = root.appender("console".to_owned());
+    }
 
-        let mut config = Config::builder().loggers(loggers);
+    let root = root.build(default_level);
 
-        let file_appender = AsyncFileAppender::builder(file_path)
-            .encoder(Box::new(make_pattern(show_thread_name)))
-            .append(false)
-            .build();
-        let file_appender = match file_appender {
-            Ok(appender) => appender,
-            Err(error) => {
-                result = Err(format!("{}", error));
-                return;
-            }
-        };
-        let file_appender = Appender::builder().build("file".to_owned(), Box::new(file_appender));
+    let mut config = Config::builder().loggers(loggers);
 
-        config = config.appender(file_appender);
+    let file_appender = AsyncFileAppender::builder(file_path)
+        .encoder(Box::new(make_pattern(show_thread_name)))
+        .append(false)
+        .build()
+        .map_err(|e| format!("{}", e))?;
+    let file_appender = Appender::builder().build("file".to_owned(), Box::new(file_appender));
 
-        if log_to_console {
-            let console_appender = AsyncConsoleAppender::builder()
-                .encoder(Box::new(make_pattern(show_thread_name)))
-                .build();
-            let console_appender =
-                Appender::builder().build("console".to_owned(), Box::new(console_appender));
+    config = config.appender(file_appender);
 
-            config = config.appender(console_appender);
-        }
+    if log_to_console {
+        let console_appender = AsyncConsoleAppender::builder()
+            .encoder(Box::new(make_pattern(show_thread_name)))
+            .build();
+        let console_appender =
+            Appender::builder().build("console".to_owned(), Box::new(console_appender));
 
-        let config = match config.build(root).map_err(|e| format!("{}", e)) {
-            Ok(config) => config,
-            Err(e) => {
-                result = Err(e);
-                return;
-            }
-        };
-        result = log4rs::init_config(config)
-            .map_err(|e| format!("{}", e))
-            .map(|_| ())
-    });
+        config = config.appender(console_appender);
+    }
 
-    result
+    let config = config.build(root).map_err(|e| format!("{}", e))?;
+
+    install(config).map(|_| ())
 }
 
 /// Initialises the `env_logger` for output to a server and optionally to the console
@@ -240,50 +322,107 @@ pub fn init_to_server<A: ToSocketAddrs>(
     show_thread_name: bool,
     log_to_console: bool,
 ) -> Result<(), String> {
-    init_once_guard(|| {
-        let (default_level, loggers) = match parse_loggers_from_env() {
-            Ok((level, loggers)) => (level, loggers),
-            Err(error) => {
-                return Err(format!("{}", error));
-            }
-        };
+    init_once_guard(|| init_to_server_impl(server_addr, show_thread_name, log_to_console))
+}
 
-        let mut root = Root::builder().appender("server".to_owned());
+/// Like [`init_to_server()`](fn.init_to_server.html), but if logging has already been initialised,
+/// replaces its configuration rather than failing.
+///
+/// See [`reinit()`](fn.reinit.html) for details.
+pub fn reinit_to_server<A: ToSocketAddrs>(
+    server_addr: A,
+    show_thread_name: bool,
+    log_to_console: bool,
+) -> Result<(), String> {
+    reinit_guard(|| init_to_server_impl(server_addr, show_thread_name, log_to_console))
+}
 
-        if log_to_console {
-            root = root.appender("console".to_owned());
+fn init_to_server_impl<A: ToSocketAddrs>(
+    server_addr: A,
+    show_thread_name: bool,
+    log_to_console: bool,
+) -> Result<(), String> {
+    let (default_level, loggers) = match parse_loggers_from_env() {
+        Ok((level, loggers)) => (level, loggers),
+        Err(error) => {
+            return Err(format!("{}", error));
         }
+    };
 
-        let root = root.build(default_level);
+    let mut root = Root::builder().appender("server".to_owned());
 
-        let mut config = Config::builder().loggers(loggers);
+    if log_to_console {
+        root = root.appender("console".to_owned());
+    }
 
-        let server_appender = AsyncServerAppender::builder(server_addr)
+    let root = root.build(default_level);
+
+    let mut config = Config::builder().loggers(loggers);
+
+    let server_appender = AsyncServerAppender::builder(server_addr)
+        .encoder(Box::new(make_pattern(show_thread_name)))
+        .build()
+        .map_err(|e| format!("{}", e))?;
+
+    let server_appender = Appender::builder().build("server".to_owned(), Box::new(server_appender));
+
+    config = config.appender(server_appender);
+
+    if log_to_console {
+        let console_appender = AsyncConsoleAppender::builder()
             .encoder(Box::new(make_pattern(show_thread_name)))
-            .build()
-            .map_err(|e| format!("{}", e))?;
+            .build();
+        let console_appender =
+            Appender::builder().build("console".to_owned(), Box::new(console_appender));
 
-        let server_appender =
-            Appender::builder().build("server".to_owned(), Box::new(server_appender));
+        config = config.appender(console_appender);
+    }
 
-        config = config.appender(server_appender);
+    let config = config.build(root).map_err(|e| format!("{}", e))?;
 
-        if log_to_console {
-            let console_appender = AsyncConsoleAppender::builder()
-                .encoder(Box::new(make_pattern(show_thread_name)))
-                .build();
-            let console_appender =
-                Appender::builder().build("console".to_owned(), Box::new(console_appender));
+    install(config).map(|_| ())
+}
 
-            config = config.appender(console_appender);
-        }
+/// Blocks until every log message queued so far has been written by the asynchronous appenders.
+pub fn flush() {
+    async_log::flush_all();
+}
+
+/// Stops watching `log.toml` for changes, then flushes the asynchronous appenders, stops their
+/// background threads and closes their files and server connections.
+///
+/// This should be called just before the process exits.  Messages logged afterwards are discarded.
+pub fn shutdown() {
+    stop_config_watcher();
+    async_log::shutdown_all();
+}
 
-        let config = config.build(root).map_err(|e| format!("{}", e))?;
+// Signals the config watcher thread to stop and waits for it to exit.
+fn stop_config_watcher() {
+    // Take the watcher out first, so that the lock isn't held while joining its thread.
+    let watcher = unwrap!(CONFIG_WATCHER.lock()).take();
+    drop(watcher);
+}
 
-        log4rs::init_config(config)
-            .map_err(|e| format!("{}", e))
-            .map(|_| ())
-    })
+fn make_deserializers(op_file_name_override: Option<String>, force_append: bool) -> Deserializers {
+    let mut deserializers = Deserializers::default();
+    deserializers.insert("async_console", AsyncConsoleAppenderCreator);
+    deserializers.insert(
+        "async_file",
+        AsyncFileAppenderCreator {
+            op_file_name_override: op_file_name_override.clone(),
+            force_append,
+        },
+    );
+    deserializers.insert(
+        "async_rolling_file",
+        AsyncRollingFileAppenderCreator {
+            op_file_name_override,
+            force_append,
+        },
+    );
+    deserializers.insert("async_server", AsyncServerAppenderCreator);
+    deserializers
 }
 
 fn make_pattern(show_thread_name: bool) -> PatternEncoder {
@@ -320,11 +459,21 @@ fn parse_loggers_from_env() -> Result<(LogLevelFilter, Vec<Logger>), ParseLogger
 }
 
 fn parse_loggers(input: &str) -> Result<(LogLevelFilter, Vec<Logger>), ParseLoggerError> {
+    let (default_level, loggers) = parse_level_spec(input, DEFAULT_LOG_LEVEL_FILTER)?;
+    Ok((default_level.unwrap_or(DEFAULT_LOG_LEVEL_FILTER), loggers))
+}
+
+// Parses `input` in `RUST_LOG` format, returning the default level if it's given.  Modules without
+// a level of their own are given the default level, or `fallback_level` if there isn't one.
+fn parse_level_spec(
+    input: &str,
+    fallback_level: LogLevelFilter,
+) -> Result<(Option<LogLevelFilter>, Vec<Logger>), ParseLoggerError> {
     use std::collections::VecDeque;
 
     let mut loggers = Vec::new();
     let mut grouped_modules = VecDeque::new();
-    let mut default_level = DEFAULT_LOG_LEVEL_FILTER;
+    let mut default_level = None;
 
     for sub_input in input.split(',').map(str::trim).filter(|d| !d.is_empty()) {
         let mut parts = sub_input.trim().split('=');
@@ -338,7 +487,7 @@ fn parse_loggers(input: &str) -> Result<(LogLevelFilter, Vec<Logger>), ParseLogg
             }
             (Some(module), None) => {
                 if let Ok(level_filter) = module.parse::<LogLevelFilter>() {
-                    default_level = level_filter;
+                    default_level = Some(level_filter);
                 } else {
                     grouped_modules.push_back(module.to_owned());
                 }
@@ -348,7 +497,7 @@ fn parse_loggers(input: &str) -> Result<(LogLevelFilter, Vec<Logger>), ParseLogg
     }
 
     while let Some(module) = grouped_modules.pop_front() {
-        loggers.push(Logger::builder().build(module, default_level));
+        loggers.push(Logger::builder().build(module, default_level.unwrap_or(fallback_level)));
     }
 
     Ok((default_level, loggers))
@@ -362,6 +511,13 @@ fn init_once_guard<F: FnOnce() -> Result<(), String>>(init_fn: F) -> Result<(),
     result
 }
 
+// Runs `init_fn` whether or not the logger has been initialised, preventing any later one-shot
+// initialisation.
+fn reinit_guard<F: FnOnce() -> Result<(), String>>(init_fn: F) -> Result<(), String> {
+    INITIALISE_LOGGER.call_once(|| ());
+    init_fn()
+}
+
 #[cfg(test)]
 mod tests {
     use super::parse_loggers;
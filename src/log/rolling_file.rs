@@ -0,0 +1,405 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// https://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+use chrono::{Date, Local, TimeZone};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// When and how a `RollingFile` is rotated.
+#[derive(Clone, Copy, Debug)]
+pub struct RollingPolicy {
+    /// Rotate before a write would take the file past this many bytes.
+    pub max_size: Option<u64>,
+    /// Rotate on the first write of each (local) day.
+    pub daily: bool,
+    /// How many rotated files to keep, as `<path>.1` (the most recent) to `<path>.<keep>`.
+    pub keep: usize,
+    /// Whether to gzip rotated files, which are then named `<path>.<n>.gz`.
+    pub compress: bool,
+}
+
+/// A log file which is rotated according to a `RollingPolicy`.  Rotation happens during writes, so
+/// on the thread doing the writing.
+pub struct RollingFile {
+    path: PathBuf,
+    policy: RollingPolicy,
+    // `None` only while rotating.
+    file: Option<File>,
+    len: u64,
+    day: Date<Local>,
+    // Set after a failed rotation until one succeeds, so that a failure is only reported once
+    // rather than on every retry, including those triggered by writing the report itself.
+    rotation_failed: bool,
+}
+
+impl RollingFile {
+    pub fn open(path: PathBuf, append: bool, policy: RollingPolicy) -> io::Result<Self> {
+        let file = open_file(&path, append)?;
+        let len = file.metadata()?.len();
+        // A reopened file is due for daily rotation if it was last written before today.
+        let day = if len > 0 {
+            modified_day(&file).unwrap_or_else(|_| Local::today())
+        } else {
+            Local::today()
+        };
+        Ok(RollingFile {
+            path,
+            policy,
+            file: Some(file),
+            len,
+            day,
+            rotation_failed: false,
+        })
+    }
+
+    /// Writes and flushes `buf`, first rotating the file if due.
+    pub fn write(&mut self, buf: &[u8]) -> io::Result<()> {
+        if self.is_rotation_due(buf.len() as u64) {
+            // If rotation fails, keep logging to the current file.
+            match self.rotate() {
+                Ok(()) => self.rotation_failed = false,
+                Err(error) => {
+                    if !self.rotation_failed {
+                        warn!("Failed to rotate {}: {}", self.path.display(), error);
+                    }
+                    self.rotation_failed = true;
+                }
+            }
+        }
+        let file = match self.file {
+            Some(ref mut file) => file,
+            None => return Err(io::Error::new(io::ErrorKind::NotFound, "log file not open")),
+        };
+        file.write_all(buf)?;
+        file.flush()?;
+        self.len += buf.len() as u64;
+        Ok(())
+    }
+
+    fn is_rotation_due(&self, write_len: u64) -> bool {
+        let too_big = match self.policy.max_size {
+            Some(max_size) => self.len > 0 && self.len + write_len > max_size,
+            None => false,
+        };
+        too_big || (self.policy.daily && Local::today() != self.day)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        // Close the file first, since open files can't be renamed on Windows.
+        self.file = None;
+        let result = self.archive();
+        // If archiving failed, `path` still holds the current file, so carry on appending to it.
+        // The day is left as it was so that a failed daily rotation is retried on the next write.
+        let file = open_file(&self.path, result.is_err())?;
+        self.len = file.metadata()?.len();
+        self.file = Some(file);
+        if result.is_ok() {
+            self.day = Local::today();
+        }
+        result
+    }
+
+    fn archive(&self) -> io::Result<()> {
+        let keep = self.policy.keep;
+        if keep == 0 {
+            return fs::remove_file(&self.path);
+        }
+        let archived = |generation: usize| {
+            let path = suffixed(&self.path, &generation.to_string());
+            if self.policy.compress {
+                suffixed(&path, "gz")
+            } else {
+                path
+            }
+        };
+
+        let shift = || {
+            ignore_not_found(fs::remove_file(archived(keep)))?;
+            for generation in (1..keep).rev() {
+                ignore_not_found(fs::rename(archived(generation), archived(generation + 1)))?;
+            }
+            Ok(())
+        };
+
+        if !self.policy.compress {
+            shift()?;
+            return fs::rename(&self.path, archived(1));
+        }
+        // Compress to a temporary file first, so that the existing archives are left untouched if
+        // that fails.
+        let compressed = suffixed(&self.path, "gz.tmp");
+        if let Err(error) = gzip(&self.path, &compressed) {
+            let _ = fs::remove_file(&compressed);
+            return Err(error);
+        }
+        // The log is removed before the archive is published, so that its lines can't end up both
+        // in an archive and in the log which is reopened if rotation fails.
+        if let Err(error) = fs::remove_file(&self.path) {
+            let _ = fs::remove_file(&compressed);
+            return Err(error);
+        }
+        let result = shift().and_then(|()| fs::rename(&compressed, archived(1)));
+        if result.is_err() {
+            // Put the log back, so that nothing is lost and rotation is retried later.
+            gunzip(&compressed, &self.path)?;
+            let _ = fs::remove_file(&compressed);
+        }
+        result
+    }
+}
+
+fn modified_day(file: &File) -> io::Result<Date<Local>> {
+    let modified = file
+        .metadata()?
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+    Ok(Local
+        .timestamp(modified.as_secs() as i64, modified.subsec_nanos())
+        .date())
+}
+
+fn open_file(path: &Path, append: bool) -> io::Result<File> {
+    if append {
+        OpenOptions::new().append(true).create(true).open(path)
+    } else {
+        OpenOptions::new()
+            .write(true)
+            .truncate(true)
+            .create(true)
+            .open(path)
+    }
+}
+
+fn gzip(source: &Path, destination: &Path) -> io::Result<()> {
+    let mut input = File::open(source)?;
+    let mut encoder = GzEncoder::new(File::create(destination)?, Compression::default());
+    let _ = io::copy(&mut input, &mut encoder)?;
+    encoder.finish()?.sync_all()
+}
+
+fn gunzip(source: &Path, destination: &Path) -> io::Result<()> {
+    let mut decoder = GzDecoder::new(File::open(source)?);
+    let mut output = File::create(destination)?;
+    let _ = io::copy(&mut decoder, &mut output)?;
+    output.sync_all()
+}
+
+fn ignore_not_found(result: io::Result<()>) -> io::Result<()> {
+    match result {
+        Err(ref error) if error.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+fn suffixed(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(".");
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::time::{Duration, SystemTime};
+    use std::{env, process};
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!(
+            "maidsafe_utilities_rolling_{}_{}",
+            name,
+            process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        unwrap!(fs::create_dir_all(&dir));
+        dir
+    }
+
+    fn read(path: &Path) -> String {
+        unwrap!(fs::read_to_string(path))
+    }
+
+    fn read_gz(path: &Path) -> String {
+        let mut decoded = String::new();
+        let _ = unwrap!(GzDecoder::new(unwrap!(File::open(path))).read_to_string(&mut decoded));
+        decoded
+    }
+
+    #[test]
+    fn rotate_on_size() {
+        let dir = temp_dir("size");
+        let path = dir.join("node.log");
+        let policy = RollingPolicy {
+            max_size: Some(10),
+            daily: false,
+            keep: 2,
+            compress: false,
+        };
+        let mut file = unwrap!(RollingFile::open(path.clone(), false, policy));
+        for line in &["first\n", "second\n", "third\n", "fourth\n"] {
+            unwrap!(file.write(line.as_bytes()));
+        }
+        assert_eq!(read(&path), "fourth\n");
+        assert_eq!(read(&suffixed(&path, "1")), "third\n");
+        assert_eq!(read(&suffixed(&path, "2")), "second\n");
+        assert!(!suffixed(&path, "3").exists());
+
+        // Records larger than the limit are written whole, to a file of their own.
+        unwrap!(file.write(b"a very long line\n"));
+        unwrap!(file.write(b"x\n"));
+        assert_eq!(read(&path), "x\n");
+        assert_eq!(read(&suffixed(&path, "1")), "a very long line\n");
+
+        // Reopening in append mode counts the existing contents.
+        let mut file = unwrap!(RollingFile::open(path.clone(), true, policy));
+        unwrap!(file.write(b"y\n"));
+        assert_eq!(read(&path), "x\ny\n");
+        unwrap!(fs::remove_dir_all(&dir));
+    }
+
+    #[test]
+    fn rotate_daily_and_compress() {
+        let dir = temp_dir("daily");
+        let path = dir.join("node.log");
+        let policy = RollingPolicy {
+            max_size: None,
+            daily: true,
+            keep: 1,
+            compress: true,
+        };
+        let mut file = unwrap!(RollingFile::open(path.clone(), false, policy));
+        unwrap!(file.write(b"first\n"));
+        unwrap!(file.write(b"second\n"));
+        assert!(!suffixed(&path, "1.gz").exists());
+
+        for contents in &["third\n", "fourth\n"] {
+            file.day = file.day.pred();
+            unwrap!(file.write(contents.as_bytes()));
+        }
+        assert_eq!(read(&path), "fourth\n");
+        assert_eq!(read_gz(&suffixed(&path, "1.gz")), "third\n");
+        assert!(!suffixed(&path, "1").exists());
+        assert!(!suffixed(&path, "2.gz").exists());
+
+        // With nothing to keep, the file is just truncated.
+        let policy = RollingPolicy { keep: 0, ..policy };
+        let mut file = unwrap!(RollingFile::open(path.clone(), true, policy));
+        file.day = file.day.pred();
+        unwrap!(file.write(b"tomorrow\n"));
+        assert_eq!(read(&path), "tomorrow\n");
+        assert_eq!(unwrap!(fs::read_dir(&dir)).count(), 2);
+        unwrap!(fs::remove_dir_all(&dir));
+    }
+
+    #[test]
+    fn reopen_stale_file() {
+        let dir = temp_dir("stale");
+        let path = dir.join("node.log");
+        let policy = RollingPolicy {
+            max_size: None,
+            daily: true,
+            keep: 1,
+            compress: false,
+        };
+        unwrap!(fs::write(&path, "yesterday\n"));
+        let two_days_ago = SystemTime::now() - Duration::from_secs(2 * 24 * 60 * 60);
+        unwrap!(unwrap!(File::options().append(true).open(&path)).set_modified(two_days_ago));
+
+        // A file last written before today is rotated on the first write after reopening it.
+        let mut file = unwrap!(RollingFile::open(path.clone(), true, policy));
+        unwrap!(file.write(b"today\n"));
+        assert_eq!(read(&path), "today\n");
+        assert_eq!(read(&suffixed(&path, "1")), "yesterday\n");
+        unwrap!(fs::remove_dir_all(&dir));
+    }
+
+    #[test]
+    fn failed_compression() {
+        let dir = temp_dir("compress");
+        let path = dir.join("node.log");
+        let policy = RollingPolicy {
+            max_size: None,
+            daily: true,
+            keep: 2,
+            compress: true,
+        };
+        let mut file = unwrap!(RollingFile::open(path.clone(), false, policy));
+        for contents in &["first\n", "second\n", "third\n"] {
+            file.day = file.day.pred();
+            unwrap!(file.write(contents.as_bytes()));
+        }
+        assert_eq!(read_gz(&suffixed(&path, "1.gz")), "second\n");
+        assert_eq!(read_gz(&suffixed(&path, "2.gz")), "first\n");
+
+        // While the file can't be compressed, the archives are left untouched and the current file
+        // is appended to, with rotation retried on each write.
+        let compressed = suffixed(&path, "gz.tmp");
+        unwrap!(fs::create_dir(&compressed));
+        let stale_day = file.day.pred();
+        file.day = stale_day;
+        unwrap!(file.write(b"fourth\n"));
+        unwrap!(file.write(b"fifth\n"));
+        assert_eq!(file.day, stale_day);
+        assert_eq!(read(&path), "third\nfourth\nfifth\n");
+        assert_eq!(read_gz(&suffixed(&path, "1.gz")), "second\n");
+        assert_eq!(read_gz(&suffixed(&path, "2.gz")), "first\n");
+
+        unwrap!(fs::remove_dir(&compressed));
+        unwrap!(file.write(b"sixth\n"));
+        assert_eq!(file.day, Local::today());
+        assert_eq!(read(&path), "sixth\n");
+        assert_eq!(read_gz(&suffixed(&path, "1.gz")), "third\nfourth\nfifth\n");
+        assert_eq!(read_gz(&suffixed(&path, "2.gz")), "second\n");
+        assert_eq!(unwrap!(fs::read_dir(&dir)).count(), 3);
+        unwrap!(fs::remove_dir_all(&dir));
+    }
+
+    #[test]
+    fn failed_publish() {
+        let dir = temp_dir("publish");
+        let path = dir.join("node.log");
+        let policy = RollingPolicy {
+            max_size: None,
+            daily: true,
+            keep: 2,
+            compress: true,
+        };
+        let mut file = unwrap!(RollingFile::open(path.clone(), false, policy));
+        unwrap!(file.write(b"first\n"));
+        file.day = file.day.pred();
+        unwrap!(file.write(b"second\n"));
+
+        // If the archives can't be shifted after the log has been compressed and removed, the log
+        // is put back and appended to, without its lines also being left in an archive.
+        let oldest = suffixed(&path, "2.gz");
+        unwrap!(fs::create_dir(&oldest));
+        file.day = file.day.pred();
+        unwrap!(file.write(b"third\n"));
+        assert!(file.rotation_failed);
+        assert_eq!(read(&path), "second\nthird\n");
+        assert_eq!(read_gz(&suffixed(&path, "1.gz")), "first\n");
+        assert!(!suffixed(&path, "gz.tmp").exists());
+
+        unwrap!(fs::remove_dir(&oldest));
+        unwrap!(file.write(b"fourth\n"));
+        assert!(!file.rotation_failed);
+        assert_eq!(read(&path), "fourth\n");
+        assert_eq!(read_gz(&suffixed(&path, "1.gz")), "second\nthird\n");
+        assert_eq!(read_gz(&suffixed(&path, "2.gz")), "first\n");
+        unwrap!(fs::remove_dir_all(&dir));
+    }
+}
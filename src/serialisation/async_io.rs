@@ -0,0 +1,289 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// https://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+use super::limits::default_limits;
+use super::{deserialise_stream, serialise_into, serialise_into_with_limit, SerialisationError};
+use bincode::{Bounded, ErrorKind};
+use futures::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use serde::de::DeserializeOwned;
+use serde::ser::Serialize;
+use std::future::Future;
+use std::io::{self, Read};
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// Serialise an `Serialize` type directly into an `AsyncWrite` with no limit on the size of the
+/// serialised data.
+///
+/// The bytes written are the same as those written by
+/// [`serialise_into()`](fn.serialise_into.html).
+pub async fn serialise_into_async<T: Serialize, W: AsyncWrite + Unpin>(
+    data: &T,
+    write: &mut W,
+) -> Result<(), SerialisationError> {
+    let mut serialised = Vec::new();
+    serialise_into(data, &mut serialised)?;
+    write_all(&serialised, write).await
+}
+
+/// Serialise an `Serialize` type directly into an `AsyncWrite` with max size limit specified.
+pub async fn serialise_into_async_with_limit<T: Serialize, W: AsyncWrite + Unpin>(
+    data: &T,
+    write: &mut W,
+    size_limit: Bounded,
+) -> Result<(), SerialisationError> {
+    let mut serialised = Vec::new();
+    serialise_into_with_limit(data, &mut serialised, size_limit)?;
+    write_all(&serialised, write).await
+}
+
+/// Deserialise a `Deserialize` type directly from an `AsyncRead`, with the process-wide
+/// [default limits](fn.set_default_limits.html).
+///
+/// Reads data written by [`serialise_into()`](fn.serialise_into.html) or
+/// [`serialise_into_async()`](fn.serialise_into_async.html), and no bytes beyond the value.
+///
+/// Bytes received so far are kept, and whenever the reader has to wait for more, decoding starts
+/// again from the first of them once it is woken.  A value arriving in `n` pieces is therefore
+/// decoded `n` times over, which for a large value trickling in from the network adds up to a cost
+/// quadratic in its size.  The reader is also asked for each primitive separately, so unless it is
+/// already buffered, wrap it in a `futures::io::BufReader`; note that this may read bytes past the
+/// value, which stay in the buffer for the next read.
+pub async fn deserialise_from_async<R: AsyncRead + Unpin, T: DeserializeOwned>(
+    read: &mut R,
+) -> Result<T, SerialisationError> {
+    deserialise_from_async_with_limit(read, Bounded(default_limits().max_bytes)).await
+}
+
+/// Deserialise a `Deserialize` type directly from an `AsyncRead` with max size limit specified.
+pub async fn deserialise_from_async_with_limit<R: AsyncRead + Unpin, T: DeserializeOwned>(
+    read: &mut R,
+    size_limit: Bounded,
+) -> Result<T, SerialisationError> {
+    ReadValue {
+        read,
+        received: Vec::new(),
+        size_limit,
+        _value: PhantomData::<fn() -> T>,
+    }
+    .await
+}
+
+async fn write_all<W: AsyncWrite + Unpin>(
+    serialised: &[u8],
+    write: &mut W,
+) -> Result<(), SerialisationError> {
+    write
+        .write_all(serialised)
+        .await
+        .map_err(|e| SerialisationError::Serialise(ErrorKind::IoError(e)))
+}
+
+// Decodes a value with the synchronous decoder, pulling input from the `AsyncRead` only as the
+// decoder asks for it, so that no bytes beyond the value are consumed.
+struct ReadValue<'a, R, T> {
+    read: &'a mut R,
+    received: Vec<u8>,
+    size_limit: Bounded,
+    _value: PhantomData<fn() -> T>,
+}
+
+impl<'a, R: AsyncRead + Unpin, T: DeserializeOwned> Future for ReadValue<'a, R, T> {
+    type Output = Result<T, SerialisationError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let mut source = Replay {
+            read: &mut *this.read,
+            cx,
+            received: &mut this.received,
+            position: 0,
+            waiting: false,
+        };
+        let result = deserialise_stream(&mut source, this.size_limit, &default_limits());
+        if source.waiting {
+            Poll::Pending
+        } else {
+            Poll::Ready(result)
+        }
+    }
+}
+
+// Replays the bytes already received, then reads on from the `AsyncRead`.  If that isn't ready,
+// the read fails and `waiting` is set, so that decoding can be abandoned and retried once woken.
+struct Replay<'a, 'b, 'c, R> {
+    read: &'a mut R,
+    cx: &'a mut Context<'b>,
+    received: &'c mut Vec<u8>,
+    position: usize,
+    waiting: bool,
+}
+
+impl<'a, 'b, 'c, R: AsyncRead + Unpin> Read for Replay<'a, 'b, 'c, R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        if self.position < self.received.len() {
+            let read = (&self.received[self.position..]).read(out)?;
+            self.position += read;
+            return Ok(read);
+        }
+        match Pin::new(&mut *self.read).poll_read(self.cx, out) {
+            Poll::Ready(Ok(read)) => {
+                self.received.extend_from_slice(&out[..read]);
+                self.position += read;
+                Ok(read)
+            }
+            Poll::Ready(Err(error)) => Err(error),
+            Poll::Pending => {
+                self.waiting = true;
+                Err(io::ErrorKind::WouldBlock.into())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serialisation::{deserialise_from, serialise};
+    use futures::executor::block_on;
+    use futures::io::{BufReader, Cursor};
+
+    // Yields its input a few bytes at a time, as a network stream might, and makes the caller wait
+    // before every other read.
+    struct Trickle<'a> {
+        data: &'a [u8],
+        ready: bool,
+    }
+
+    impl<'a> Trickle<'a> {
+        fn new(data: &'a [u8]) -> Self {
+            Trickle { data, ready: false }
+        }
+    }
+
+    impl<'a> AsyncRead for Trickle<'a> {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context,
+            out: &mut [u8],
+        ) -> Poll<io::Result<usize>> {
+            if !self.ready {
+                self.ready = true;
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+            self.ready = false;
+            let len = out.len().min(self.data.len()).min(3);
+            out[..len].copy_from_slice(&self.data[..len]);
+            self.data = &self.data[len..];
+            Poll::Ready(Ok(len))
+        }
+    }
+
+    #[test]
+    fn async_round_trip() {
+        let first = (vec![0u8, 1, 3, 9], "SomeString".to_string());
+        let second = vec![-1i64, 888, -8765];
+
+        let mut serialised = Cursor::new(Vec::new());
+        unwrap!(block_on(serialise_into_async(&first, &mut serialised)));
+        unwrap!(block_on(serialise_into_async_with_limit(
+            &second,
+            &mut serialised,
+            Bounded(32)
+        )));
+        let serialised = serialised.into_inner();
+        assert_eq!(
+            serialised,
+            [unwrap!(serialise(&first)), unwrap!(serialise(&second))].concat()
+        );
+
+        // Values are read back one at a time without consuming the bytes which follow them.
+        let mut read = Trickle::new(&serialised);
+        let deserialised: (Vec<u8>, String) = unwrap!(block_on(deserialise_from_async(&mut read)));
+        assert_eq!(first, deserialised);
+        let deserialised: Vec<i64> = unwrap!(block_on(deserialise_from_async_with_limit(
+            &mut read,
+            Bounded(32)
+        )));
+        assert_eq!(second, deserialised);
+
+        match unwrap_err!(block_on(deserialise_from_async::<_, u64>(&mut read))) {
+            SerialisationError::Deserialise(ErrorKind::IoError(_), _) => (),
+            err => panic!("{:?}", err),
+        }
+    }
+
+    #[test]
+    fn async_upper_limit() {
+        let data = (1u64..9).collect::<Vec<_>>();
+        let mut buffer = Cursor::new(Vec::new());
+        match unwrap_err!(block_on(serialise_into_async_with_limit(
+            &data,
+            &mut buffer,
+            Bounded(64)
+        ))) {
+            SerialisationError::Serialise(ErrorKind::SizeLimit) => (),
+            err => panic!("{:?}", err),
+        }
+        assert!(buffer.get_ref().is_empty());
+
+        let serialised = unwrap!(serialise(&data));
+        let mut read = Trickle::new(&serialised);
+        match unwrap_err!(block_on(deserialise_from_async_with_limit::<_, Vec<u64>>(
+            &mut read,
+            Bounded(64)
+        ))) {
+            SerialisationError::Deserialise(ErrorKind::SizeLimit, _) => (),
+            err => panic!("{:?}", err),
+        }
+    }
+
+    #[test]
+    fn sync_write_async_read() {
+        let values = vec![(1u32, "one".to_owned()), (2, "two".to_owned())];
+        let mut serialised = Vec::new();
+        for value in &values {
+            unwrap!(serialise_into(value, &mut serialised));
+        }
+
+        let mut read = Trickle::new(&serialised);
+        for value in &values {
+            let deserialised: (u32, String) = unwrap!(block_on(deserialise_from_async(&mut read)));
+            assert_eq!(&deserialised, value);
+        }
+        assert!(read.data.is_empty());
+
+        // Bytes buffered past the first value are used for the next.
+        let mut read = BufReader::new(Trickle::new(&serialised));
+        for value in &values {
+            let deserialised: (u32, String) = unwrap!(block_on(deserialise_from_async(&mut read)));
+            assert_eq!(&deserialised, value);
+        }
+        assert!(read.get_ref().data.is_empty());
+    }
+
+    #[test]
+    fn async_write_sync_read() {
+        let values = vec![(1u32, "one".to_owned()), (2, "two".to_owned())];
+        let mut serialised = Cursor::new(Vec::new());
+        for value in &values {
+            unwrap!(block_on(serialise_into_async(value, &mut serialised)));
+        }
+
+        let serialised = serialised.into_inner();
+        let mut read = &serialised[..];
+        for value in &values {
+            let deserialised: (u32, String) = unwrap!(deserialise_from(&mut read));
+            assert_eq!(&deserialised, value);
+        }
+        assert!(read.is_empty());
+    }
+}
@@ -0,0 +1,491 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// https://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+use super::SerialisationError;
+use bincode::{serialize_into, ErrorKind, Infinite};
+use serde::ser::{
+    self, Serialize, SerializeMap, SerializeSeq, SerializeStruct, SerializeStructVariant,
+    SerializeTuple, SerializeTupleStruct, SerializeTupleVariant, Serializer,
+};
+
+// Name of the newtype struct which marks a sequence as a set, so that `CanonicalSerializer` sorts
+// its elements.  Other serializers treat a newtype struct as its contents, so ignore the marker.
+const CANONICAL_SET: &str = "$maidsafe_utilities::canonical_set";
+
+/// Serialise an `Serialize` type into canonical bytes.
+///
+/// The output is valid bincode which can be read back with [`deserialise()`](fn.deserialise.html),
+/// but the entries of every map are ordered by their encoded key bytes, so that logically equal
+/// maps (e.g. two `HashMap`s with different iteration orders) always produce identical bytes.
+///
+/// Serde cannot tell a set apart from any other sequence, so fields holding a `HashSet` must be
+/// annotated with `#[serde(serialize_with = "canonical_set")]`, or with
+/// `#[serde(serialize_with = "canonical_set_values")]` for a map whose values are sets, to be
+/// ordered likewise.
+pub fn serialise_canonical<T: Serialize>(data: &T) -> Result<Vec<u8>, SerialisationError> {
+    let mut output = Vec::new();
+    data.serialize(CanonicalSerializer::new(&mut output))
+        .map_err(|e| SerialisationError::Serialise(*e))?;
+    Ok(output)
+}
+
+/// Marks the items of a set so that [`serialise_canonical()`](fn.serialise_canonical.html)
+/// orders them by their canonical encoding.  Other serializers write them in iteration order, as
+/// for any other sequence.
+///
+/// Intended for use as `#[serde(serialize_with = "canonical_set")]` on `HashSet` fields.
+pub fn canonical_set<'a, I, T, S>(set: I, serializer: S) -> Result<S::Ok, S::Error>
+where
+    I: IntoIterator<Item = &'a T>,
+    T: Serialize + 'a,
+    S: Serializer,
+{
+    let items = set.into_iter().collect::<Vec<_>>();
+    serializer.serialize_newtype_struct(CANONICAL_SET, &items)
+}
+
+/// As [`canonical_set()`](fn.canonical_set.html), but for each value of a map whose values are
+/// sets, e.g. a `HashMap<String, HashSet<u64>>`.
+///
+/// Intended for use as `#[serde(serialize_with = "canonical_set_values")]`.
+pub fn canonical_set_values<'a, M, K, V, T, S>(map: M, serializer: S) -> Result<S::Ok, S::Error>
+where
+    M: IntoIterator<Item = (&'a K, &'a V)>,
+    K: Serialize + 'a,
+    V: 'a,
+    &'a V: IntoIterator<Item = &'a T>,
+    T: Serialize + 'a,
+    S: Serializer,
+{
+    serializer.collect_map(
+        map.into_iter()
+            .map(|(key, set)| (key, CanonicalSet(set.into_iter().collect()))),
+    )
+}
+
+/// The items of a set, serialised as by `canonical_set()`.
+struct CanonicalSet<'a, T>(Vec<&'a T>);
+
+impl<'a, T: Serialize> Serialize for CanonicalSet<'a, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_struct(CANONICAL_SET, &self.0)
+    }
+}
+
+/// Bincode-compatible serializer which orders map entries by their encoded key bytes, and the
+/// elements of sets marked by `canonical_set()` by their encoded bytes.
+struct CanonicalSerializer<'a> {
+    output: &'a mut Vec<u8>,
+    // Whether the value is a set, so its elements are to be sorted.
+    set: bool,
+}
+
+impl<'a> CanonicalSerializer<'a> {
+    fn new(output: &'a mut Vec<u8>) -> Self {
+        CanonicalSerializer { output, set: false }
+    }
+
+    // Writes a primitive value using bincode's encoding.
+    fn primitive<T: Serialize>(self, value: &T) -> bincode::Result<()> {
+        serialize_into(self.output, value, Infinite)
+    }
+
+    fn length(self, len: Option<usize>, sorted: bool) -> bincode::Result<Compound<'a>> {
+        let len = len.ok_or(ErrorKind::SequenceMustHaveLength)?;
+        serialize_into(self.output, &(len as u64), Infinite)?;
+        Ok(Compound::new(self.output, sorted))
+    }
+
+    fn variant(self, variant_index: u32) -> bincode::Result<Compound<'a>> {
+        serialize_into(self.output, &variant_index, Infinite)?;
+        Ok(Compound::new(self.output, false))
+    }
+}
+
+impl<'a> Serializer for CanonicalSerializer<'a> {
+    type Ok = ();
+    type Error = bincode::Error;
+    type SerializeSeq = Compound<'a>;
+    type SerializeTuple = Compound<'a>;
+    type SerializeTupleStruct = Compound<'a>;
+    type SerializeTupleVariant = Compound<'a>;
+    type SerializeMap = Compound<'a>;
+    type SerializeStruct = Compound<'a>;
+    type SerializeStructVariant = Compound<'a>;
+
+    fn serialize_bool(self, v: bool) -> bincode::Result<()> {
+        self.primitive(&v)
+    }
+
+    fn serialize_i8(self, v: i8) -> bincode::Result<()> {
+        self.primitive(&v)
+    }
+
+    fn serialize_i16(self, v: i16) -> bincode::Result<()> {
+        self.primitive(&v)
+    }
+
+    fn serialize_i32(self, v: i32) -> bincode::Result<()> {
+        self.primitive(&v)
+    }
+
+    fn serialize_i64(self, v: i64) -> bincode::Result<()> {
+        self.primitive(&v)
+    }
+
+    fn serialize_u8(self, v: u8) -> bincode::Result<()> {
+        self.primitive(&v)
+    }
+
+    fn serialize_u16(self, v: u16) -> bincode::Result<()> {
+        self.primitive(&v)
+    }
+
+    fn serialize_u32(self, v: u32) -> bincode::Result<()> {
+        self.primitive(&v)
+    }
+
+    fn serialize_u64(self, v: u64) -> bincode::Result<()> {
+        self.primitive(&v)
+    }
+
+    fn serialize_f32(self, v: f32) -> bincode::Result<()> {
+        self.primitive(&v)
+    }
+
+    fn serialize_f64(self, v: f64) -> bincode::Result<()> {
+        self.primitive(&v)
+    }
+
+    fn serialize_char(self, v: char) -> bincode::Result<()> {
+        self.primitive(&v)
+    }
+
+    fn serialize_str(self, v: &str) -> bincode::Result<()> {
+        self.primitive(&v)
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> bincode::Result<()> {
+        self.primitive(&Bytes(v))
+    }
+
+    fn serialize_none(self) -> bincode::Result<()> {
+        self.primitive(&0u8)
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> bincode::Result<()> {
+        self.output.push(1);
+        value.serialize(CanonicalSerializer::new(self.output))
+    }
+
+    fn serialize_unit(self) -> bincode::Result<()> {
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> bincode::Result<()> {
+        Ok(())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+    ) -> bincode::Result<()> {
+        self.primitive(&variant_index)
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        name: &'static str,
+        value: &T,
+    ) -> bincode::Result<()> {
+        value.serialize(CanonicalSerializer {
+            output: self.output,
+            set: name == CANONICAL_SET,
+        })
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        value: &T,
+    ) -> bincode::Result<()> {
+        serialize_into(self.output, &variant_index, Infinite)?;
+        value.serialize(CanonicalSerializer::new(self.output))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> bincode::Result<Compound<'a>> {
+        let set = self.set;
+        self.length(len, set)
+    }
+
+    fn serialize_tuple(self, _len: usize) -> bincode::Result<Compound<'a>> {
+        Ok(Compound::new(self.output, false))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> bincode::Result<Compound<'a>> {
+        Ok(Compound::new(self.output, false))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> bincode::Result<Compound<'a>> {
+        self.variant(variant_index)
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> bincode::Result<Compound<'a>> {
+        self.length(len, true)
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> bincode::Result<Compound<'a>> {
+        Ok(Compound::new(self.output, false))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> bincode::Result<Compound<'a>> {
+        self.variant(variant_index)
+    }
+}
+
+/// Serializer state for compound types.  The entries of maps and sets are buffered and only
+/// written, in order of their encoded keys (or elements), when the map or set ends.
+struct Compound<'a> {
+    output: &'a mut Vec<u8>,
+    sorted: bool,
+    entries: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+impl<'a> Compound<'a> {
+    fn new(output: &'a mut Vec<u8>, sorted: bool) -> Self {
+        Compound {
+            output,
+            sorted,
+            entries: Vec::new(),
+        }
+    }
+
+    fn element<T: ?Sized + Serialize>(&mut self, value: &T) -> bincode::Result<()> {
+        if self.sorted {
+            let mut encoded = Vec::new();
+            value.serialize(CanonicalSerializer::new(&mut encoded))?;
+            self.entries.push((encoded, Vec::new()));
+            return Ok(());
+        }
+        value.serialize(CanonicalSerializer::new(self.output))
+    }
+
+    fn end(mut self) -> bincode::Result<()> {
+        self.entries.sort_by(|lhs, rhs| lhs.0.cmp(&rhs.0));
+        for (key, value) in self.entries {
+            self.output.extend_from_slice(&key);
+            self.output.extend_from_slice(&value);
+        }
+        Ok(())
+    }
+}
+
+impl<'a> SerializeSeq for Compound<'a> {
+    type Ok = ();
+    type Error = bincode::Error;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> bincode::Result<()> {
+        self.element(value)
+    }
+
+    fn end(self) -> bincode::Result<()> {
+        Compound::end(self)
+    }
+}
+
+impl<'a> SerializeTuple for Compound<'a> {
+    type Ok = ();
+    type Error = bincode::Error;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> bincode::Result<()> {
+        self.element(value)
+    }
+
+    fn end(self) -> bincode::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> SerializeTupleStruct for Compound<'a> {
+    type Ok = ();
+    type Error = bincode::Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> bincode::Result<()> {
+        self.element(value)
+    }
+
+    fn end(self) -> bincode::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> SerializeTupleVariant for Compound<'a> {
+    type Ok = ();
+    type Error = bincode::Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> bincode::Result<()> {
+        self.element(value)
+    }
+
+    fn end(self) -> bincode::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> SerializeMap for Compound<'a> {
+    type Ok = ();
+    type Error = bincode::Error;
+
+    fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> bincode::Result<()> {
+        self.element(key)
+    }
+
+    fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> bincode::Result<()> {
+        let entry = self.entries.last_mut().ok_or_else(|| {
+            ErrorKind::Custom("`serialize_value` called before `serialize_key`".to_owned())
+        })?;
+        value.serialize(CanonicalSerializer::new(&mut entry.1))
+    }
+
+    fn end(self) -> bincode::Result<()> {
+        Compound::end(self)
+    }
+}
+
+impl<'a> SerializeStruct for Compound<'a> {
+    type Ok = ();
+    type Error = bincode::Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        _key: &'static str,
+        value: &T,
+    ) -> bincode::Result<()> {
+        self.element(value)
+    }
+
+    fn end(self) -> bincode::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> SerializeStructVariant for Compound<'a> {
+    type Ok = ();
+    type Error = bincode::Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        _key: &'static str,
+        value: &T,
+    ) -> bincode::Result<()> {
+        self.element(value)
+    }
+
+    fn end(self) -> bincode::Result<()> {
+        Ok(())
+    }
+}
+
+/// Forwards to `serialize_bytes`, since `&[u8]` on its own serialises as a sequence.
+struct Bytes<'a>(&'a [u8]);
+
+impl<'a> Serialize for Bytes<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serialisation::testing::assert_canonical_round_trip;
+    use crate::serialisation::{deserialise, serialise};
+    use serde_derive::{Deserialize, Serialize};
+    use std::collections::{BTreeMap, HashMap, HashSet};
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct Index {
+        entries: HashMap<String, Vec<u32>>,
+        #[serde(serialize_with = "canonical_set")]
+        members: HashSet<u64>,
+        nested: Option<BTreeMap<i32, HashMap<u8, char>>>,
+        #[serde(serialize_with = "canonical_set_values")]
+        groups: HashMap<u8, HashSet<String>>,
+    }
+
+    fn index(insertion_order: &[usize]) -> Index {
+        let keys = ["alpha", "beta", "gamma", "delta", "epsilon"];
+        let mut entries = HashMap::new();
+        let mut members = HashSet::new();
+        let mut inner = HashMap::new();
+        let mut groups = HashMap::new();
+        for &i in insertion_order {
+            let _ = entries.insert(keys[i].to_owned(), vec![i as u32; i]);
+            let _ = members.insert(1000 - i as u64);
+            let _ = inner.insert(i as u8, 'x');
+            for &j in insertion_order {
+                let _ = groups
+                    .entry(i as u8 % 2)
+                    .or_insert_with(HashSet::new)
+                    .insert(keys[j].repeat(i + 1));
+            }
+        }
+        let mut nested = BTreeMap::new();
+        let _ = nested.insert(-1, inner);
+        Index {
+            entries,
+            members,
+            nested: Some(nested),
+            groups,
+        }
+    }
+
+    #[test]
+    fn canonical_bytes_are_order_independent() {
+        let forwards = index(&[0, 1, 2, 3, 4]);
+        let backwards = index(&[4, 3, 2, 1, 0]);
+        assert_eq!(forwards, backwards);
+
+        let canonical = unwrap!(serialise_canonical(&forwards));
+        assert_eq!(canonical, unwrap!(serialise_canonical(&backwards)));
+        assert_eq!(
+            canonical.len() as u64,
+            crate::serialisation::serialised_size(&forwards)
+        );
+        assert_eq!(forwards, unwrap!(deserialise::<Index>(&canonical)));
+        assert_canonical_round_trip(&forwards);
+
+        // Types without maps produce plain bincode.
+        let plain = (1u8, "two".to_owned(), vec![3i64], Some('4'), [5u16; 2]);
+        assert_eq!(
+            unwrap!(serialise_canonical(&plain)),
+            unwrap!(serialise(&plain))
+        );
+    }
+}
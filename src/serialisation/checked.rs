@@ -0,0 +1,261 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// https://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+use super::{
+    deserialise_from, deserialise_from_with_limit, serialise_into, serialise_into_with_limit,
+    Bincode, Format, SerialisationError,
+};
+use bincode::{Bounded, ErrorKind};
+use crc32c::{crc32c, crc32c_append};
+use serde::de::DeserializeOwned;
+use serde::ser::Serialize;
+use std::io::{self, Read, Write};
+
+/// Length of the CRC32C checksum appended to checked payloads.
+pub const CHECKSUM_LEN: usize = 4;
+
+/// Serialise an `Serialize` type with a trailing CRC32C checksum and no limit on the size of the
+/// serialised data.
+pub fn serialise_checked<T: Serialize>(data: &T) -> Result<Vec<u8>, SerialisationError> {
+    Ok(with_checksum(Bincode::serialise(data, None)?))
+}
+
+/// Serialise an `Serialize` type with a trailing CRC32C checksum and max limit specified.
+///
+/// The limit applies to the payload and excludes the checksum.
+pub fn serialise_checked_with_limit<T: Serialize>(
+    data: &T,
+    size_limit: Bounded,
+) -> Result<Vec<u8>, SerialisationError> {
+    Ok(with_checksum(Bincode::serialise(data, Some(size_limit))?))
+}
+
+/// Deserialise a `Deserialize` type written by
+/// [`serialise_checked()`](fn.serialise_checked.html) with the process-wide [default
+/// limits](fn.set_default_limits.html).
+///
+/// The checksum is verified before any decoding is attempted.
+pub fn deserialise_checked<T>(data: &[u8]) -> Result<T, SerialisationError>
+where
+    T: DeserializeOwned,
+{
+    Bincode::deserialise(verify_checksum(data)?, None)
+}
+
+/// Deserialise a `Deserialize` type written by
+/// [`serialise_checked()`](fn.serialise_checked.html) with max size limit specified.
+///
+/// The checksum is verified before any decoding is attempted.
+pub fn deserialise_checked_with_limit<T>(
+    data: &[u8],
+    size_limit: Bounded,
+) -> Result<T, SerialisationError>
+where
+    T: DeserializeOwned,
+{
+    Bincode::deserialise(verify_checksum(data)?, Some(size_limit))
+}
+
+/// Serialise an `Serialize` type directly into a `Write` followed by a CRC32C checksum, with no
+/// limit on the size of the serialised data.
+pub fn serialise_checked_into<T: Serialize, W: Write>(
+    data: &T,
+    write: &mut W,
+) -> Result<(), SerialisationError> {
+    let mut writer = ChecksumWriter::new(write);
+    serialise_into(data, &mut writer)?;
+    writer.finish()
+}
+
+/// Serialise an `Serialize` type directly into a `Write` followed by a CRC32C checksum, with max
+/// size limit specified.
+pub fn serialise_checked_into_with_limit<T: Serialize, W: Write>(
+    data: &T,
+    write: &mut W,
+    size_limit: Bounded,
+) -> Result<(), SerialisationError> {
+    let mut writer = ChecksumWriter::new(write);
+    serialise_into_with_limit(data, &mut writer, size_limit)?;
+    writer.finish()
+}
+
+/// Deserialise a `Deserialize` type directly from a `Read` and verify its trailing CRC32C
+/// checksum, with the process-wide [default limits](fn.set_default_limits.html).
+///
+/// Unlike the slice variant, the checksum can only be verified once the value has been decoded, so
+/// corruption which breaks decoding is reported as a `Deserialise` error.
+pub fn deserialise_checked_from<R: Read, T: DeserializeOwned>(
+    read: &mut R,
+) -> Result<T, SerialisationError> {
+    let mut reader = ChecksumReader::new(read);
+    let value = deserialise_from(&mut reader)?;
+    reader.finish()?;
+    Ok(value)
+}
+
+/// Deserialise a `Deserialize` type directly from a `Read` and verify its trailing CRC32C
+/// checksum, with max size limit specified.
+pub fn deserialise_checked_from_with_limit<R: Read, T: DeserializeOwned>(
+    read: &mut R,
+    size_limit: Bounded,
+) -> Result<T, SerialisationError> {
+    let mut reader = ChecksumReader::new(read);
+    let value = deserialise_from_with_limit(&mut reader, size_limit)?;
+    reader.finish()?;
+    Ok(value)
+}
+
+fn with_checksum(mut payload: Vec<u8>) -> Vec<u8> {
+    let checksum = crc32c(&payload);
+    payload.extend_from_slice(&checksum.to_le_bytes());
+    payload
+}
+
+// Returns the payload of `data` if its trailing CRC32C checksum is correct.
+pub(super) fn verify_checksum(data: &[u8]) -> Result<&[u8], SerialisationError> {
+    if data.len() < CHECKSUM_LEN {
+        return Err(SerialisationError::ChecksumMismatch);
+    }
+    let (payload, checksum) = data.split_at(data.len() - CHECKSUM_LEN);
+    if crc32c(payload).to_le_bytes() != checksum {
+        return Err(SerialisationError::ChecksumMismatch);
+    }
+    Ok(payload)
+}
+
+struct ChecksumWriter<'a, W> {
+    inner: &'a mut W,
+    crc: u32,
+}
+
+impl<'a, W: Write> ChecksumWriter<'a, W> {
+    fn new(inner: &'a mut W) -> Self {
+        ChecksumWriter { inner, crc: 0 }
+    }
+
+    fn finish(self) -> Result<(), SerialisationError> {
+        self.inner
+            .write_all(&self.crc.to_le_bytes())
+            .map_err(|e| SerialisationError::Serialise(ErrorKind::IoError(e)))
+    }
+}
+
+impl<'a, W: Write> Write for ChecksumWriter<'a, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.crc = crc32c_append(self.crc, &buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+struct ChecksumReader<'a, R> {
+    inner: &'a mut R,
+    crc: u32,
+}
+
+impl<'a, R: Read> ChecksumReader<'a, R> {
+    fn new(inner: &'a mut R) -> Self {
+        ChecksumReader { inner, crc: 0 }
+    }
+
+    fn finish(self) -> Result<(), SerialisationError> {
+        let mut checksum = [0; CHECKSUM_LEN];
+        self.inner
+            .read_exact(&mut checksum)
+            .map_err(|e| SerialisationError::Deserialise(ErrorKind::IoError(e), None))?;
+        if self.crc.to_le_bytes() != checksum {
+            return Err(SerialisationError::ChecksumMismatch);
+        }
+        Ok(())
+    }
+}
+
+impl<'a, R: Read> Read for ChecksumReader<'a, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.crc = crc32c_append(self.crc, &buf[..read]);
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serialisation::serialised_size;
+    use std::io::Cursor;
+
+    #[test]
+    fn checked_slices() {
+        let original_data = (vec![0u8, 1, 3, 9], 0x0102_0304u32, "SomeString".to_string());
+        let mut serialised = unwrap!(serialise_checked(&original_data));
+        assert_eq!(
+            serialised.len() as u64,
+            serialised_size(&original_data) + CHECKSUM_LEN as u64
+        );
+        let deserialised: (Vec<u8>, u32, String) = unwrap!(deserialise_checked(&serialised));
+        assert_eq!(original_data, deserialised);
+
+        // Flip a bit inside the integer, which would otherwise decode successfully.
+        serialised[12] ^= 0x10;
+        match unwrap_err!(deserialise_checked::<(Vec<u8>, u32, String)>(&serialised)) {
+            SerialisationError::ChecksumMismatch => (),
+            err => panic!("{:?}", err),
+        }
+        match unwrap_err!(deserialise_checked_with_limit::<(Vec<u8>, u32, String)>(
+            &serialised,
+            Bounded(1024)
+        )) {
+            SerialisationError::ChecksumMismatch => (),
+            err => panic!("{:?}", err),
+        }
+        match unwrap_err!(deserialise_checked::<u8>(&[0, 1])) {
+            SerialisationError::ChecksumMismatch => (),
+            err => panic!("{:?}", err),
+        }
+    }
+
+    #[test]
+    fn checked_streams() {
+        let original_data = (vec![-1i64, 888, -8765], "SomeString".to_string());
+        let mut buffer = vec![];
+        unwrap!(serialise_checked_into(&original_data, &mut buffer));
+        unwrap!(serialise_checked_into_with_limit(
+            &original_data,
+            &mut buffer,
+            Bounded(64)
+        ));
+        // Both encodings match the slice variant.
+        let expected = unwrap!(serialise_checked(&original_data));
+        assert_eq!(buffer[..expected.len()], expected[..]);
+        assert_eq!(buffer[expected.len()..], expected[..]);
+
+        let mut stream = Cursor::new(buffer.clone());
+        let first: (Vec<i64>, String) = unwrap!(deserialise_checked_from(&mut stream));
+        let second: (Vec<i64>, String) = unwrap!(deserialise_checked_from_with_limit(
+            &mut stream,
+            Bounded(64)
+        ));
+        assert_eq!(original_data, first);
+        assert_eq!(original_data, second);
+
+        // Corrupt the first value's last integer.
+        buffer[30] ^= 0x01;
+        let mut stream = Cursor::new(buffer);
+        match unwrap_err!(deserialise_checked_from::<_, (Vec<i64>, String)>(
+            &mut stream
+        )) {
+            SerialisationError::ChecksumMismatch => (),
+            err => panic!("{:?}", err),
+        }
+    }
+}
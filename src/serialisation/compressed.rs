@@ -0,0 +1,172 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// https://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+use super::limits::default_limits;
+use super::{Bincode, Format, SerialisationError};
+use bincode::{Bounded, ErrorKind};
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use serde::de::DeserializeOwned;
+use serde::ser::Serialize;
+use std::io::{Read, Write};
+
+/// Serialised payloads smaller than this many bytes are stored uncompressed.
+pub const COMPRESSION_THRESHOLD: usize = 512;
+
+const FLAG_UNCOMPRESSED: u8 = 0;
+const FLAG_DEFLATE: u8 = 1;
+
+/// Serialise an `Serialize` type and compress it with deflate, with no limit on the size of the
+/// serialised data.
+///
+/// Payloads smaller than `COMPRESSION_THRESHOLD`, or which don't shrink when compressed, are stored
+/// uncompressed.
+pub fn serialise_compressed<T: Serialize>(data: &T) -> Result<Vec<u8>, SerialisationError> {
+    compress(Bincode::serialise(data, None)?)
+}
+
+/// Serialise an `Serialize` type and compress it with deflate, with max limit specified.
+///
+/// The limit applies to the uncompressed serialised data.
+pub fn serialise_compressed_with_limit<T: Serialize>(
+    data: &T,
+    size_limit: Bounded,
+) -> Result<Vec<u8>, SerialisationError> {
+    compress(Bincode::serialise(data, Some(size_limit))?)
+}
+
+/// Decompress and deserialise a `Deserialize` type written by
+/// [`serialise_compressed()`](fn.serialise_compressed.html), with the process-wide [default
+/// limits](fn.set_default_limits.html).
+///
+/// As with [`deserialise_compressed_with_limit()`](fn.deserialise_compressed_with_limit.html), the
+/// default byte limit applies to the decompressed data.
+pub fn deserialise_compressed<T: DeserializeOwned>(data: &[u8]) -> Result<T, SerialisationError> {
+    deserialise_compressed_with_limit(data, Bounded(default_limits().max_bytes))
+}
+
+/// Decompress and deserialise a `Deserialize` type written by
+/// [`serialise_compressed()`](fn.serialise_compressed.html), with max size limit specified.
+///
+/// The limit applies to the *decompressed* data: decompression stops with
+/// `ErrorKind::SizeLimit` as soon as it would produce more than `size_limit` bytes.
+pub fn deserialise_compressed_with_limit<T: DeserializeOwned>(
+    data: &[u8],
+    size_limit: Bounded,
+) -> Result<T, SerialisationError> {
+    let (flag, payload) = split_flag(data)?;
+    if flag == FLAG_UNCOMPRESSED {
+        return Bincode::deserialise(payload, Some(size_limit));
+    }
+    let mut decompressed = Vec::new();
+    let _ = DeflateDecoder::new(payload)
+        .take(size_limit.0.saturating_add(1))
+        .read_to_end(&mut decompressed)
+        .map_err(|e| SerialisationError::Deserialise(ErrorKind::IoError(e), None))?;
+    if decompressed.len() as u64 > size_limit.0 {
+        return Err(SerialisationError::Deserialise(ErrorKind::SizeLimit, None));
+    }
+    Bincode::deserialise(&decompressed, Some(size_limit))
+}
+
+fn compress(serialised: Vec<u8>) -> Result<Vec<u8>, SerialisationError> {
+    if serialised.len() >= COMPRESSION_THRESHOLD {
+        let mut encoder = DeflateEncoder::new(vec![FLAG_DEFLATE], Compression::default());
+        let compressed = encoder
+            .write_all(&serialised)
+            .and_then(|()| encoder.finish())
+            .map_err(|e| SerialisationError::Serialise(ErrorKind::IoError(e)))?;
+        if compressed.len() <= serialised.len() {
+            return Ok(compressed);
+        }
+    }
+    let mut uncompressed = Vec::with_capacity(serialised.len() + 1);
+    uncompressed.push(FLAG_UNCOMPRESSED);
+    uncompressed.extend_from_slice(&serialised);
+    Ok(uncompressed)
+}
+
+fn split_flag(data: &[u8]) -> Result<(u8, &[u8]), SerialisationError> {
+    match data.split_first() {
+        Some((&flag, payload)) if flag == FLAG_UNCOMPRESSED || flag == FLAG_DEFLATE => {
+            Ok((flag, payload))
+        }
+        Some((&flag, _)) => Err(SerialisationError::Deserialise(
+            ErrorKind::InvalidEncoding {
+                desc: "unknown compression flag",
+                detail: Some(format!("flag byte {}", flag)),
+            },
+            None,
+        )),
+        None => Err(SerialisationError::Deserialise(
+            ErrorKind::IoError(std::io::ErrorKind::UnexpectedEof.into()),
+            None,
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serialisation::serialise;
+
+    #[test]
+    fn compressed_round_trip() {
+        // Small payloads are stored as-is.
+        let small = vec![7u8; 16];
+        let serialised = unwrap!(serialise_compressed(&small));
+        assert_eq!(serialised[0], FLAG_UNCOMPRESSED);
+        assert_eq!(serialised[1..], unwrap!(serialise(&small))[..]);
+        assert_eq!(
+            small,
+            unwrap!(deserialise_compressed::<Vec<u8>>(&serialised))
+        );
+
+        // Large repetitive payloads are compressed.
+        let large = vec![7u64; 4096];
+        let serialised = unwrap!(serialise_compressed(&large));
+        assert_eq!(serialised[0], FLAG_DEFLATE);
+        assert!(serialised.len() < 1024);
+        assert_eq!(
+            large,
+            unwrap!(deserialise_compressed::<Vec<u64>>(&serialised))
+        );
+        assert_eq!(
+            large,
+            unwrap!(deserialise_compressed_with_limit::<Vec<u64>>(
+                &serialised,
+                Bounded(8 + 8 * 4096)
+            ))
+        );
+
+        match unwrap_err!(deserialise_compressed::<Vec<u64>>(&[9, 0])) {
+            SerialisationError::Deserialise(ErrorKind::InvalidEncoding { .. }, _) => (),
+            err => panic!("{:?}", err),
+        }
+    }
+
+    #[test]
+    fn decompression_bomb() {
+        // Roughly 16 KiB which expands to 16 MiB.
+        let bomb = vec![0u8; 16 * 1024 * 1024];
+        let mut encoder = DeflateEncoder::new(vec![FLAG_DEFLATE], Compression::best());
+        unwrap!(encoder.write_all(&bomb));
+        let compressed = unwrap!(encoder.finish());
+        assert!(compressed.len() < 32 * 1024);
+
+        match unwrap_err!(deserialise_compressed_with_limit::<Vec<u8>>(
+            &compressed,
+            Bounded(1024 * 1024)
+        )) {
+            SerialisationError::Deserialise(ErrorKind::SizeLimit, _) => (),
+            err => panic!("{:?}", err),
+        }
+    }
+}
@@ -0,0 +1,340 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// https://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+use super::limits::default_limits;
+#[cfg(any(feature = "json", feature = "msgpack", feature = "cbor"))]
+use super::tracked::{Limiter, TrackedSeed};
+use super::{deserialise_slice, SerialisationError};
+use bincode::{serialize, Bounded, ErrorKind, Infinite};
+use serde::de::DeserializeOwned;
+#[cfg(any(feature = "json", feature = "msgpack", feature = "cbor"))]
+use serde::de::{Deserialize, DeserializeSeed, Deserializer};
+use serde::ser::Serialize;
+#[cfg(any(feature = "json", feature = "msgpack", feature = "cbor"))]
+use std::marker::PhantomData;
+
+/// A wire format which values can be serialised to and deserialised from.
+///
+/// Errors from every format are reported as `SerialisationError::Serialise` or
+/// `SerialisationError::Deserialise`, or as `CollectionTooLong` or `NestingTooDeep` if decoding
+/// exceeds the process-wide [default limits](fn.set_default_limits.html).  For formats other than
+/// `Bincode`, the size limit is checked against the length of the encoded data.
+pub trait Format {
+    /// Serialise `data`, failing with `ErrorKind::SizeLimit` if the output would exceed
+    /// `size_limit`.
+    fn serialise<T: Serialize>(
+        data: &T,
+        size_limit: Option<Bounded>,
+    ) -> Result<Vec<u8>, SerialisationError>;
+
+    /// Deserialise a value from `data`, which must be consumed in full, failing with
+    /// `ErrorKind::SizeLimit` if decoding would exceed `size_limit`, or the default limit if
+    /// `None`.
+    fn deserialise<T: DeserializeOwned>(
+        data: &[u8],
+        size_limit: Option<Bounded>,
+    ) -> Result<T, SerialisationError>;
+}
+
+/// The compact bincode format, used by [`serialise()`](fn.serialise.html) and every other function
+/// in this module which doesn't take a `Format`.
+pub struct Bincode;
+
+impl Format for Bincode {
+    fn serialise<T: Serialize>(
+        data: &T,
+        size_limit: Option<Bounded>,
+    ) -> Result<Vec<u8>, SerialisationError> {
+        match size_limit {
+            Some(size_limit) => serialize(data, size_limit),
+            None => serialize(data, Infinite),
+        }
+        .map_err(|e| SerialisationError::Serialise(*e))
+    }
+
+    fn deserialise<T: DeserializeOwned>(
+        data: &[u8],
+        size_limit: Option<Bounded>,
+    ) -> Result<T, SerialisationError> {
+        let limits = default_limits();
+        deserialise_slice(
+            data,
+            size_limit.unwrap_or(Bounded(limits.max_bytes)),
+            &limits,
+        )
+    }
+}
+
+/// Human-readable JSON, useful for debugging and config dumps.
+#[cfg(feature = "json")]
+pub struct Json;
+
+#[cfg(feature = "json")]
+impl Format for Json {
+    fn serialise<T: Serialize>(
+        data: &T,
+        size_limit: Option<Bounded>,
+    ) -> Result<Vec<u8>, SerialisationError> {
+        let serialised = serde_json::to_vec(data).map_err(serialise_error)?;
+        check_output_size(serialised, size_limit)
+    }
+
+    fn deserialise<T: DeserializeOwned>(
+        data: &[u8],
+        size_limit: Option<Bounded>,
+    ) -> Result<T, SerialisationError> {
+        check_input_size(data, size_limit)?;
+        let mut deserializer = serde_json::Deserializer::from_slice(data);
+        let value = Limited::deserialize(&mut deserializer)
+            .map_err(deserialise_error)?
+            .0?;
+        // Rejects any trailing non-whitespace input.
+        deserializer.end().map_err(deserialise_error)?;
+        Ok(value)
+    }
+}
+
+/// MessagePack, with structs encoded as maps keyed by field name.
+#[cfg(feature = "msgpack")]
+pub struct MessagePack;
+
+#[cfg(feature = "msgpack")]
+impl Format for MessagePack {
+    fn serialise<T: Serialize>(
+        data: &T,
+        size_limit: Option<Bounded>,
+    ) -> Result<Vec<u8>, SerialisationError> {
+        let serialised = rmp_serde::to_vec_named(data).map_err(serialise_error)?;
+        check_output_size(serialised, size_limit)
+    }
+
+    fn deserialise<T: DeserializeOwned>(
+        data: &[u8],
+        size_limit: Option<Bounded>,
+    ) -> Result<T, SerialisationError> {
+        check_input_size(data, size_limit)?;
+        let mut remaining = data;
+        let value = rmp_serde::from_read::<_, Limited<T>>(&mut remaining)
+            .map_err(deserialise_error)?
+            .0?;
+        check_consumed(remaining)?;
+        Ok(value)
+    }
+}
+
+/// CBOR (RFC 8949), for interoperability with non-Rust clients.
+#[cfg(feature = "cbor")]
+pub struct Cbor;
+
+#[cfg(feature = "cbor")]
+impl Format for Cbor {
+    fn serialise<T: Serialize>(
+        data: &T,
+        size_limit: Option<Bounded>,
+    ) -> Result<Vec<u8>, SerialisationError> {
+        let mut serialised = Vec::new();
+        ciborium::ser::into_writer(data, &mut serialised).map_err(serialise_error)?;
+        check_output_size(serialised, size_limit)
+    }
+
+    fn deserialise<T: DeserializeOwned>(
+        data: &[u8],
+        size_limit: Option<Bounded>,
+    ) -> Result<T, SerialisationError> {
+        check_input_size(data, size_limit)?;
+        let mut remaining = data;
+        let value = ciborium::de::from_reader::<Limited<T>, _>(&mut remaining)
+            .map_err(deserialise_error)?
+            .0?;
+        check_consumed(remaining)?;
+        Ok(value)
+    }
+}
+
+/// Serialise an `Serialize` type using the given `Format` with no limit on the size of the
+/// serialised data.
+pub fn serialise_as<F: Format, T: Serialize>(data: &T) -> Result<Vec<u8>, SerialisationError> {
+    F::serialise(data, None)
+}
+
+/// Serialise an `Serialize` type using the given `Format` with max limit specified.
+pub fn serialise_as_with_limit<F: Format, T: Serialize>(
+    data: &T,
+    size_limit: Bounded,
+) -> Result<Vec<u8>, SerialisationError> {
+    F::serialise(data, Some(size_limit))
+}
+
+/// Deserialise a `Deserialize` type using the given `Format` with the process-wide [default
+/// limits](fn.set_default_limits.html).
+pub fn deserialise_as<F: Format, T: DeserializeOwned>(
+    data: &[u8],
+) -> Result<T, SerialisationError> {
+    F::deserialise(data, None)
+}
+
+/// Deserialise a `Deserialize` type using the given `Format` with max size limit specified.
+pub fn deserialise_as_with_limit<F: Format, T: DeserializeOwned>(
+    data: &[u8],
+    size_limit: Bounded,
+) -> Result<T, SerialisationError> {
+    F::deserialise(data, Some(size_limit))
+}
+
+// Decodes a `T`, enforcing the default collection length and nesting depth limits.  Exceeding one
+// is returned as the value, since the format's own error type can't carry it.
+#[cfg(any(feature = "json", feature = "msgpack", feature = "cbor"))]
+struct Limited<T>(Result<T, SerialisationError>);
+
+#[cfg(any(feature = "json", feature = "msgpack", feature = "cbor"))]
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Limited<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let limiter = Limiter::new(&default_limits());
+        match TrackedSeed::new(PhantomData::<T>, &limiter).deserialize(deserializer) {
+            Ok(value) => Ok(Limited(Ok(value))),
+            Err(error) if limiter.is_exceeded() => Ok(Limited(Err(
+                limiter.into_error(ErrorKind::Custom(error.to_string()), None)
+            ))),
+            Err(error) => Err(error),
+        }
+    }
+}
+
+#[cfg(any(feature = "json", feature = "msgpack", feature = "cbor"))]
+fn serialise_error<E: std::fmt::Display>(error: E) -> SerialisationError {
+    SerialisationError::Serialise(ErrorKind::Custom(error.to_string()))
+}
+
+#[cfg(any(feature = "json", feature = "msgpack", feature = "cbor"))]
+fn deserialise_error<E: std::fmt::Display>(error: E) -> SerialisationError {
+    SerialisationError::Deserialise(ErrorKind::Custom(error.to_string()), None)
+}
+
+#[cfg(any(feature = "json", feature = "msgpack", feature = "cbor"))]
+fn check_output_size(
+    serialised: Vec<u8>,
+    size_limit: Option<Bounded>,
+) -> Result<Vec<u8>, SerialisationError> {
+    match size_limit {
+        Some(Bounded(max)) if serialised.len() as u64 > max => {
+            Err(SerialisationError::Serialise(ErrorKind::SizeLimit))
+        }
+        _ => Ok(serialised),
+    }
+}
+
+#[cfg(any(feature = "json", feature = "msgpack", feature = "cbor"))]
+fn check_input_size(data: &[u8], size_limit: Option<Bounded>) -> Result<(), SerialisationError> {
+    let max = match size_limit {
+        Some(Bounded(max)) => max,
+        None => default_limits().max_bytes,
+    };
+    if data.len() as u64 > max {
+        return Err(SerialisationError::Deserialise(ErrorKind::SizeLimit, None));
+    }
+    Ok(())
+}
+
+#[cfg(any(feature = "msgpack", feature = "cbor"))]
+fn check_consumed(remaining: &[u8]) -> Result<(), SerialisationError> {
+    if remaining.is_empty() {
+        Ok(())
+    } else {
+        Err(SerialisationError::DeserialiseExtraBytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serialisation::serialise;
+    use serde_derive::{Deserialize, Serialize};
+    use std::collections::BTreeMap;
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct Config {
+        name: String,
+        ports: Vec<u16>,
+        tags: BTreeMap<String, bool>,
+    }
+
+    fn config() -> Config {
+        let mut tags = BTreeMap::new();
+        let _ = tags.insert("seed".to_owned(), true);
+        Config {
+            name: "vault".to_owned(),
+            ports: vec![5483, 5484],
+            tags,
+        }
+    }
+
+    fn round_trip<F: Format>() -> Vec<u8> {
+        let original = config();
+        let serialised = unwrap!(serialise_as::<F, _>(&original));
+        assert_eq!(original, unwrap!(deserialise_as::<F, Config>(&serialised)));
+
+        let limit = Bounded(serialised.len() as u64);
+        let _ = unwrap!(serialise_as_with_limit::<F, _>(&original, limit));
+        let _ = unwrap!(deserialise_as_with_limit::<F, Config>(&serialised, limit));
+
+        let too_small = Bounded(serialised.len() as u64 - 1);
+        match unwrap_err!(serialise_as_with_limit::<F, _>(&original, too_small)) {
+            SerialisationError::Serialise(ErrorKind::SizeLimit) => (),
+            err => panic!("{:?}", err),
+        }
+        match unwrap_err!(deserialise_as_with_limit::<F, Config>(
+            &serialised,
+            too_small
+        )) {
+            SerialisationError::Deserialise(ErrorKind::SizeLimit, _) => (),
+            err => panic!("{:?}", err),
+        }
+
+        let mut extended = serialised.clone();
+        extended.push(0);
+        match unwrap_err!(deserialise_as::<F, Config>(&extended)) {
+            SerialisationError::DeserialiseExtraBytes | SerialisationError::Deserialise(..) => (),
+            err => panic!("{:?}", err),
+        }
+
+        match unwrap_err!(deserialise_as::<F, Config>(&serialised[1..])) {
+            SerialisationError::Deserialise(..) => (),
+            err => panic!("{:?}", err),
+        }
+        serialised
+    }
+
+    #[test]
+    fn bincode_format() {
+        let serialised = round_trip::<Bincode>();
+        assert_eq!(serialised, unwrap!(serialise(&config())));
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn json_format() {
+        let serialised = round_trip::<Json>();
+        assert_eq!(
+            unwrap!(String::from_utf8(serialised)),
+            r#"{"name":"vault","ports":[5483,5484],"tags":{"seed":true}}"#
+        );
+    }
+
+    #[cfg(feature = "msgpack")]
+    #[test]
+    fn msgpack_format() {
+        let _ = round_trip::<MessagePack>();
+    }
+
+    #[cfg(feature = "cbor")]
+    #[test]
+    fn cbor_format() {
+        let _ = round_trip::<Cbor>();
+    }
+}
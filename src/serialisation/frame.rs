@@ -0,0 +1,413 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// https://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+use super::{serialised_size, Bincode, Format, SerialisationError};
+use bincode::{Bounded, ErrorKind};
+use serde::de::DeserializeOwned;
+use serde::ser::Serialize;
+use std::io::{self, Read, Write};
+
+/// Length of the little-endian `u32` prefix written before every frame.
+pub const FRAME_HEADER_LEN: usize = 4;
+
+// The most read from the underlying reader at once, both into a frame's buffer and to discard the
+// payload of an oversized frame.
+const READ_CHUNK_LEN: usize = 4096;
+
+/// Writes length-prefixed frames to a `Write`.
+///
+/// Frames which cannot be written immediately because the writer would block are buffered, and
+/// are written out by subsequent calls to [`write_frame()`](#method.write_frame) or
+/// [`flush()`](#method.flush).
+pub struct FrameWriter<W> {
+    writer: W,
+    max_frame_size: u64,
+    pending: Vec<u8>,
+}
+
+impl<W: Write> FrameWriter<W> {
+    /// Creates a new `FrameWriter` which rejects frames with payloads larger than
+    /// `max_frame_size`.
+    pub fn new(writer: W, max_frame_size: Bounded) -> Self {
+        FrameWriter {
+            writer,
+            max_frame_size: max_frame_size.0.min(u64::from(u32::MAX)),
+            pending: Vec::new(),
+        }
+    }
+
+    /// Serialises `data` as a single frame, then writes out as much buffered data as the
+    /// underlying writer will currently accept.
+    pub fn write_frame<T: Serialize>(&mut self, data: &T) -> Result<(), SerialisationError> {
+        self.write_frame_unflushed(data)?;
+        let _ = self.flush()?;
+        Ok(())
+    }
+
+    // Like `write_frame()`, but leaves flushing the underlying writer to the caller, so that a run
+    // of frames costs a single flush.
+    pub(crate) fn write_frame_unflushed<T: Serialize>(
+        &mut self,
+        data: &T,
+    ) -> Result<(), SerialisationError> {
+        let payload = match Bincode::serialise(data, Some(Bounded(self.max_frame_size))) {
+            Ok(payload) => payload,
+            Err(SerialisationError::Serialise(ErrorKind::SizeLimit)) => {
+                return Err(SerialisationError::FrameTooLarge(
+                    serialised_size(data),
+                    self.max_frame_size,
+                ));
+            }
+            Err(error) => return Err(error),
+        };
+        self.pending
+            .extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        self.pending.extend_from_slice(&payload);
+        let _ = self.write_pending()?;
+        Ok(())
+    }
+
+    /// Writes out as much buffered data as possible and flushes the underlying writer.
+    ///
+    /// Returns `true` if all buffered frames have been written, or `false` if the writer would
+    /// block.
+    pub fn flush(&mut self) -> Result<bool, SerialisationError> {
+        if !self.write_pending()? {
+            return Ok(false);
+        }
+        match self.writer.flush() {
+            Ok(()) => Ok(true),
+            Err(ref error) if error.kind() == io::ErrorKind::WouldBlock => Ok(false),
+            Err(error) => Err(serialise_error(error)),
+        }
+    }
+
+    // Writes out as much buffered data as possible, returning `false` if the writer would block.
+    fn write_pending(&mut self) -> Result<bool, SerialisationError> {
+        while !self.pending.is_empty() {
+            match self.writer.write(&self.pending) {
+                Ok(0) => {
+                    return Err(serialise_error(io::Error::new(
+                        io::ErrorKind::WriteZero,
+                        "failed to write frame",
+                    )));
+                }
+                Ok(written) => {
+                    let _ = self.pending.drain(..written);
+                }
+                Err(ref error) if error.kind() == io::ErrorKind::Interrupted => (),
+                Err(ref error) if error.kind() == io::ErrorKind::WouldBlock => return Ok(false),
+                Err(error) => return Err(serialise_error(error)),
+            }
+        }
+        Ok(true)
+    }
+
+    /// Returns the number of bytes buffered but not yet written.
+    pub fn pending_bytes(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Returns a reference to the underlying writer.
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    /// Unwraps this `FrameWriter`, returning the underlying writer and discarding any buffered
+    /// data.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Reads length-prefixed frames written by a [`FrameWriter`](struct.FrameWriter.html) from a
+/// `Read`.
+///
+/// Partially received frames are buffered, so the reader may be non-blocking: if a frame is not
+/// yet complete, [`read_frame()`](#method.read_frame) returns `Ok(None)` and can be called again
+/// once more data is available.  No bytes beyond the end of the current frame are read from the
+/// underlying reader.
+///
+/// A frame larger than the limit is reported once as `SerialisationError::FrameTooLarge`, and its
+/// payload is then discarded without being buffered, so the following frames can still be read.
+pub struct FrameReader<R> {
+    reader: R,
+    max_frame_size: u64,
+    buffer: Vec<u8>,
+    frame_len: Option<usize>,
+    // Length of the oversized frame being skipped, and how many of its bytes are still to come.
+    skip_len: u64,
+    skip_remaining: u64,
+    closed: bool,
+}
+
+impl<R: Read> FrameReader<R> {
+    /// Creates a new `FrameReader` which rejects frames with payloads larger than
+    /// `max_frame_size`.
+    pub fn new(reader: R, max_frame_size: Bounded) -> Self {
+        FrameReader {
+            reader,
+            max_frame_size: max_frame_size.0,
+            buffer: Vec::new(),
+            frame_len: None,
+            skip_len: 0,
+            skip_remaining: 0,
+            closed: false,
+        }
+    }
+
+    /// Reads and deserialises the next frame.
+    ///
+    /// Returns `Ok(None)` if no complete frame is available yet because the reader would block, or
+    /// if the stream ended cleanly on a frame boundary; use [`is_closed()`](#method.is_closed) to
+    /// tell the two apart.  A stream which ends part-way through a frame yields
+    /// `SerialisationError::TruncatedFrame`.
+    pub fn read_frame<T: DeserializeOwned>(&mut self) -> Result<Option<T>, SerialisationError> {
+        if !self.skip_oversized_frame()? {
+            return Ok(None);
+        }
+        loop {
+            let wanted = match self.frame_len {
+                Some(frame_len) => frame_len,
+                None => FRAME_HEADER_LEN,
+            };
+            if self.buffer.len() == wanted {
+                match self.frame_len.take() {
+                    None => {
+                        let mut header = [0; FRAME_HEADER_LEN];
+                        header.copy_from_slice(&self.buffer);
+                        let frame_len = u64::from(u32::from_le_bytes(header));
+                        if frame_len > self.max_frame_size {
+                            self.buffer.clear();
+                            self.skip_len = frame_len;
+                            self.skip_remaining = frame_len;
+                            return Err(SerialisationError::FrameTooLarge(
+                                frame_len,
+                                self.max_frame_size,
+                            ));
+                        }
+                        self.buffer.clear();
+                        self.frame_len = Some(frame_len as usize);
+                    }
+                    Some(_) => {
+                        let result =
+                            Bincode::deserialise(&self.buffer, Some(Bounded(self.max_frame_size)));
+                        self.buffer.clear();
+                        return result.map(Some);
+                    }
+                }
+                continue;
+            }
+
+            // The buffer grows as data arrives, rather than being allocated up front from the
+            // untrusted header.
+            let filled = self.buffer.len();
+            self.buffer
+                .resize(filled + (wanted - filled).min(READ_CHUNK_LEN), 0);
+            let result = self.reader.read(&mut self.buffer[filled..]);
+            let read = match result {
+                Ok(read) => read,
+                Err(_) => 0,
+            };
+            self.buffer.truncate(filled + read);
+            match result {
+                Ok(0) => {
+                    self.closed = true;
+                    if filled == 0 && self.frame_len.is_none() {
+                        return Ok(None);
+                    }
+                    let expected = FRAME_HEADER_LEN + self.frame_len.unwrap_or(0);
+                    let received = filled + self.frame_len.map_or(0, |_| FRAME_HEADER_LEN);
+                    return Err(SerialisationError::TruncatedFrame(
+                        received as u64,
+                        expected as u64,
+                    ));
+                }
+                Ok(_) => (),
+                Err(ref error) if error.kind() == io::ErrorKind::Interrupted => (),
+                Err(ref error) if error.kind() == io::ErrorKind::WouldBlock => return Ok(None),
+                Err(error) => {
+                    return Err(SerialisationError::Deserialise(
+                        ErrorKind::IoError(error),
+                        None,
+                    ));
+                }
+            }
+        }
+    }
+
+    // Discards the rest of an oversized frame's payload, returning `false` if the reader would block
+    // before it has all been read.
+    fn skip_oversized_frame(&mut self) -> Result<bool, SerialisationError> {
+        let mut scratch = [0; READ_CHUNK_LEN];
+        while self.skip_remaining > 0 {
+            let len = self.skip_remaining.min(READ_CHUNK_LEN as u64) as usize;
+            match self.reader.read(&mut scratch[..len]) {
+                Ok(0) => {
+                    self.closed = true;
+                    let expected = FRAME_HEADER_LEN as u64 + self.skip_len;
+                    let received = expected - self.skip_remaining;
+                    self.skip_remaining = 0;
+                    return Err(SerialisationError::TruncatedFrame(received, expected));
+                }
+                Ok(read) => self.skip_remaining -= read as u64,
+                Err(ref error) if error.kind() == io::ErrorKind::Interrupted => (),
+                Err(ref error) if error.kind() == io::ErrorKind::WouldBlock => return Ok(false),
+                Err(error) => {
+                    return Err(SerialisationError::Deserialise(
+                        ErrorKind::IoError(error),
+                        None,
+                    ));
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    /// Returns `true` if the underlying reader has reached end-of-stream.
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    /// Returns a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+    /// Unwraps this `FrameReader`, returning the underlying reader and discarding any partially
+    /// received frame.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+fn serialise_error(error: io::Error) -> SerialisationError {
+    SerialisationError::Serialise(ErrorKind::IoError(error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cmp;
+    use std::io::Cursor;
+
+    // Reader which yields at most `chunk` bytes per call, and reports `WouldBlock` on every
+    // other call.
+    struct Trickle {
+        data: Cursor<Vec<u8>>,
+        chunk: usize,
+        block: bool,
+    }
+
+    impl Read for Trickle {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.block = !self.block;
+            if self.block {
+                return Err(io::Error::new(io::ErrorKind::WouldBlock, "try again"));
+            }
+            let len = cmp::min(self.chunk, buf.len());
+            self.data.read(&mut buf[..len])
+        }
+    }
+
+    #[test]
+    fn frames_over_non_blocking_reader() {
+        let mut writer = FrameWriter::new(Vec::new(), Bounded(64));
+        unwrap!(writer.write_frame(&"first".to_owned()));
+        unwrap!(writer.write_frame(&(1u64..5).collect::<Vec<_>>()));
+        assert_eq!(writer.pending_bytes(), 0);
+        let written = writer.into_inner();
+        assert_eq!(written[..FRAME_HEADER_LEN], [13, 0, 0, 0]);
+
+        let mut reader = FrameReader::new(
+            Trickle {
+                data: Cursor::new(written),
+                chunk: 3,
+                block: false,
+            },
+            Bounded(64),
+        );
+        let mut frames = Vec::new();
+        while !reader.is_closed() {
+            if let Some(frame) = unwrap!(reader.read_frame::<String>()) {
+                frames.push(frame);
+                break;
+            }
+        }
+        let mut numbers = None;
+        while numbers.is_none() {
+            numbers = unwrap!(reader.read_frame::<Vec<u64>>());
+        }
+        assert_eq!(frames, vec!["first".to_owned()]);
+        assert_eq!(unwrap!(numbers), vec![1, 2, 3, 4]);
+
+        while !reader.is_closed() {
+            assert!(unwrap!(reader.read_frame::<String>()).is_none());
+        }
+    }
+
+    #[test]
+    fn oversized_and_truncated_frames() {
+        let mut writer = FrameWriter::new(Vec::new(), Bounded(8));
+        match unwrap_err!(writer.write_frame(&(1u64..3).collect::<Vec<_>>())) {
+            SerialisationError::FrameTooLarge(24, 8) => (),
+            err => panic!("{:?}", err),
+        }
+        assert_eq!(writer.pending_bytes(), 0);
+
+        let mut writer = FrameWriter::new(Vec::new(), Bounded(64));
+        unwrap!(writer.write_frame(&(1u64..3).collect::<Vec<_>>()));
+        let mut written = writer.into_inner();
+
+        // The oversized frame is skipped, leaving the reader usable.
+        let mut writer = FrameWriter::new(written.clone(), Bounded(64));
+        unwrap!(writer.write_frame(&7u32));
+        let mut reader = FrameReader::new(Cursor::new(writer.into_inner()), Bounded(8));
+        match unwrap_err!(reader.read_frame::<Vec<u64>>()) {
+            SerialisationError::FrameTooLarge(24, 8) => (),
+            err => panic!("{:?}", err),
+        }
+        assert_eq!(unwrap!(reader.read_frame::<u32>()), Some(7));
+        assert!(unwrap!(reader.read_frame::<u32>()).is_none());
+        assert!(reader.is_closed());
+
+        let mut reader = FrameReader::new(Cursor::new(written[..12].to_vec()), Bounded(8));
+        let _ = unwrap_err!(reader.read_frame::<Vec<u64>>());
+        match unwrap_err!(reader.read_frame::<Vec<u64>>()) {
+            SerialisationError::TruncatedFrame(12, 28) => (),
+            err => panic!("{:?}", err),
+        }
+
+        written.truncate(20);
+        let mut reader = FrameReader::new(Cursor::new(written), Bounded(64));
+        match unwrap_err!(reader.read_frame::<Vec<u64>>()) {
+            SerialisationError::TruncatedFrame(20, 28) => (),
+            err => panic!("{:?}", err),
+        }
+
+        // A header announcing a large frame doesn't cause it to be allocated before it arrives.
+        let mut reader = FrameReader::new(
+            Trickle {
+                data: Cursor::new(vec![0, 0, 0, 64, 1, 2, 3]),
+                chunk: 7,
+                block: false,
+            },
+            Bounded(1 << 30),
+        );
+        while !reader.is_closed() {
+            if let Err(error) = reader.read_frame::<Vec<u8>>() {
+                match error {
+                    SerialisationError::TruncatedFrame(7, 1_073_741_828) => (),
+                    err => panic!("{:?}", err),
+                }
+            }
+            assert!(reader.buffer.capacity() <= 2 * READ_CHUNK_LEN);
+        }
+    }
+}
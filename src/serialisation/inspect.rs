@@ -0,0 +1,249 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// https://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+use super::limits::default_limits;
+use super::reader::SliceReader;
+use super::tracked::{TrackedSeed, Tracker};
+use super::SerialisationError;
+use bincode::{Bounded, Deserializer};
+use serde::de::{Deserialize, DeserializeSeed};
+use std::fmt::{self, Display, Formatter};
+use std::marker::PhantomData;
+
+// Longer values only have their first bytes shown in the dump.
+const MAX_DUMPED_BYTES: usize = 16;
+
+/// A single value decoded by [`inspect()`](fn.inspect.html), along with the bytes it was decoded
+/// from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InspectedValue {
+    offset: u64,
+    bytes: Vec<u8>,
+    path: String,
+    type_name: &'static str,
+    value: String,
+}
+
+impl InspectedValue {
+    /// Offset of the value's first byte in the inspected data.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// The raw bytes making up the value, including any length prefix or tag.
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Fields, elements and enum variants leading to the value, e.g.
+    /// `Message.body.entries[3].name`.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Name of the value's type, as given by `std::any::type_name()`.
+    pub fn type_name(&self) -> &'static str {
+        self.type_name
+    }
+
+    /// The decoded value.  Collections show their length, options `Some` or `None`, and enums the
+    /// name of their variant.
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+}
+
+/// Annotated dump of serialised data, produced by [`inspect()`](fn.inspect.html).
+///
+/// Its `Display` implementation prints one line per value, giving its offset, raw bytes, path and
+/// decoded value, followed by any bytes which weren't decoded and the error which stopped decoding.
+#[derive(Debug)]
+pub struct Inspection {
+    data: Vec<u8>,
+    values: Vec<InspectedValue>,
+    error: Option<SerialisationError>,
+}
+
+impl Inspection {
+    /// The decoded values, in the order in which they appear in the data.
+    pub fn values(&self) -> &[InspectedValue] {
+        &self.values
+    }
+
+    /// The error which stopped decoding, or `None` if the data decoded successfully and in full.
+    pub fn error(&self) -> Option<&SerialisationError> {
+        self.error.as_ref()
+    }
+
+    /// Number of bytes covered by the decoded values.
+    pub fn decoded_len(&self) -> usize {
+        self.values
+            .last()
+            .map_or(0, |value| value.offset as usize + value.bytes.len())
+    }
+}
+
+impl Display for Inspection {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        for value in &self.values {
+            let annotation = if value.path.is_empty() {
+                value.value.clone()
+            } else {
+                format!("{} = {}", value.path, value.value)
+            };
+            write_line(formatter, value.offset as usize, &value.bytes, &annotation)?;
+        }
+        let decoded_len = self.decoded_len();
+        if decoded_len < self.data.len() {
+            let remaining = &self.data[decoded_len..];
+            let annotation = format!("<{} bytes not decoded>", remaining.len());
+            write_line(formatter, decoded_len, remaining, &annotation)?;
+        }
+        if let Some(ref error) = self.error {
+            writeln!(formatter, "{}", error)?;
+        }
+        Ok(())
+    }
+}
+
+fn write_line(
+    formatter: &mut Formatter,
+    offset: usize,
+    bytes: &[u8],
+    annotation: &str,
+) -> fmt::Result {
+    let mut hex = bytes
+        .iter()
+        .take(MAX_DUMPED_BYTES)
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<Vec<_>>()
+        .join(" ");
+    if bytes.len() > MAX_DUMPED_BYTES {
+        hex.push_str(" ..");
+    }
+    writeln!(formatter, "{:>8}  {:<50}  {}", offset, hex, annotation)
+}
+
+/// Decodes `data` as a `T`, returning an annotated dump of every value read from it.
+///
+/// If decoding fails, the dump covers the values decoded up to that point and records the error.
+/// The process-wide [default limits](fn.set_default_limits.html) apply.
+pub fn inspect<'a, T: Deserialize<'a>>(data: &'a [u8]) -> Inspection {
+    let limits = default_limits();
+    let reader = SliceReader::new(data);
+    let position = || reader.position();
+    let tracker = Tracker::annotating(&position, &limits);
+    let result = TrackedSeed::new(PhantomData::<T>, &tracker)
+        .deserialize(&mut Deserializer::new(&reader, Bounded(limits.max_bytes)));
+
+    let values = tracker
+        .take_annotations()
+        .into_iter()
+        .map(|annotation| InspectedValue {
+            offset: annotation.start as u64,
+            bytes: data[annotation.start..annotation.end].to_vec(),
+            path: annotation.path,
+            type_name: annotation.type_name,
+            value: annotation.value,
+        })
+        .collect();
+    let error = match result {
+        Ok(_) if reader.position() != data.len() => Some(SerialisationError::DeserialiseExtraBytes),
+        Ok(_) => None,
+        Err(error) => Some(tracker.into_error(*error)),
+    };
+    Inspection {
+        data: data.to_vec(),
+        values,
+        error,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serialisation::serialise;
+    use bincode::ErrorKind;
+    use serde_derive::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize)]
+    struct Peer {
+        id: u16,
+        name: String,
+        addr: Option<[u8; 4]>,
+    }
+
+    #[test]
+    fn annotated_dump() {
+        let peer = Peer {
+            id: 7,
+            name: "vault".to_owned(),
+            addr: Some([127, 0, 0, 1]),
+        };
+        let serialised = unwrap!(serialise(&peer));
+        let inspection = inspect::<Peer>(&serialised);
+        assert!(inspection.error().is_none());
+        assert_eq!(inspection.decoded_len(), serialised.len());
+
+        let summary = inspection
+            .values()
+            .iter()
+            .map(|value| (value.offset(), value.path(), value.value()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![
+                (0, "Peer.id", "7"),
+                (2, "Peer.name", "\"vault\""),
+                (15, "Peer.addr", "Some"),
+                (16, "Peer.addr[0]", "127"),
+                (17, "Peer.addr[1]", "0"),
+                (18, "Peer.addr[2]", "0"),
+                (19, "Peer.addr[3]", "1"),
+            ]
+        );
+        assert_eq!(inspection.values()[0].bytes(), &[7, 0]);
+        assert!(inspection.values()[1].type_name().ends_with("String"));
+
+        let dump = inspection.to_string();
+        assert!(dump.contains(
+            "       2  05 00 00 00 00 00 00 00 76 61 75 6c 74              Peer.name = \"vault\""
+        ));
+    }
+
+    #[test]
+    fn partial_dump() {
+        let peer = Peer {
+            id: 7,
+            name: "vault".to_owned(),
+            addr: None,
+        };
+        let mut serialised = unwrap!(serialise(&peer));
+        // An invalid tag for the `Option`.
+        let last = serialised.len() - 1;
+        serialised[last] = 9;
+        serialised.extend_from_slice(&[1, 2, 3]);
+
+        let inspection = inspect::<Peer>(&serialised);
+        assert_eq!(inspection.values().len(), 2);
+        assert_eq!(inspection.decoded_len(), last);
+        match inspection.error() {
+            Some(SerialisationError::Deserialise(
+                ErrorKind::InvalidEncoding { .. },
+                Some(context),
+            )) => {
+                assert_eq!(context.path(), "Peer.addr")
+            }
+            error => panic!("{:?}", error),
+        }
+        let dump = inspection.to_string();
+        assert!(dump.contains("      15  09 01 02 03"));
+        assert!(dump.contains("<4 bytes not decoded>"));
+    }
+}
@@ -0,0 +1,61 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// https://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+use std::sync::RwLock;
+
+/// Limits applied when deserialising untrusted input.
+///
+/// The process-wide defaults are used by [`deserialise()`](fn.deserialise.html),
+/// [`deserialise_from()`](fn.deserialise_from.html) and every other deserialising function which
+/// doesn't take a limit.  Functions taking a `Bounded` size limit use it in place of `max_bytes`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DeserialiseLimits {
+    /// Maximum number of input bytes which may be consumed.  Exceeding it fails with
+    /// `SerialisationError::Deserialise(ErrorKind::SizeLimit, _)`.
+    pub max_bytes: u64,
+    /// Maximum number of elements in any one sequence or map.  Exceeding it fails with
+    /// `SerialisationError::CollectionTooLong`.
+    pub max_collection_len: u64,
+    /// Maximum depth to which values may be nested.  Exceeding it fails with
+    /// `SerialisationError::NestingTooDeep`.
+    pub max_depth: usize,
+}
+
+impl Default for DeserialiseLimits {
+    /// 16 MiB of input, collections of up to as many elements as that has bytes, and 128 levels
+    /// of nesting.
+    fn default() -> Self {
+        DeserialiseLimits {
+            max_bytes: 16 * 1024 * 1024,
+            max_collection_len: 16 * 1024 * 1024,
+            max_depth: 128,
+        }
+    }
+}
+
+// Used for trusted input.
+pub const UNBOUNDED: DeserialiseLimits = DeserialiseLimits {
+    max_bytes: u64::MAX,
+    max_collection_len: u64::MAX,
+    max_depth: usize::MAX,
+};
+
+lazy_static! {
+    static ref DEFAULT_LIMITS: RwLock<DeserialiseLimits> = RwLock::new(Default::default());
+}
+
+/// Sets the limits used by every deserialising function which doesn't take a limit.
+pub fn set_default_limits(limits: DeserialiseLimits) {
+    *unwrap!(DEFAULT_LIMITS.write()) = limits;
+}
+
+/// Returns the limits used by every deserialising function which doesn't take a limit.
+pub fn default_limits() -> DeserialiseLimits {
+    *unwrap!(DEFAULT_LIMITS.read())
+}
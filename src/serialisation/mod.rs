@@ -0,0 +1,904 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// https://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+#[cfg(feature = "async")]
+pub use self::async_io::{
+    deserialise_from_async, deserialise_from_async_with_limit, serialise_into_async,
+    serialise_into_async_with_limit,
+};
+pub use self::canonical::{canonical_set, canonical_set_values, serialise_canonical};
+pub use self::checked::{
+    deserialise_checked, deserialise_checked_from, deserialise_checked_from_with_limit,
+    deserialise_checked_with_limit, serialise_checked, serialise_checked_into,
+    serialise_checked_into_with_limit, serialise_checked_with_limit, CHECKSUM_LEN,
+};
+pub use self::compressed::{
+    deserialise_compressed, deserialise_compressed_with_limit, serialise_compressed,
+    serialise_compressed_with_limit, COMPRESSION_THRESHOLD,
+};
+#[cfg(feature = "cbor")]
+pub use self::format::Cbor;
+#[cfg(feature = "json")]
+pub use self::format::Json;
+#[cfg(feature = "msgpack")]
+pub use self::format::MessagePack;
+pub use self::format::{
+    deserialise_as, deserialise_as_with_limit, serialise_as, serialise_as_with_limit, Bincode,
+    Format,
+};
+pub use self::frame::{FrameReader, FrameWriter, FRAME_HEADER_LEN};
+pub use self::inspect::{inspect, InspectedValue, Inspection};
+pub use self::limits::{default_limits, set_default_limits, DeserialiseLimits};
+pub use self::persist::{
+    read_file, read_file_with_backups, read_file_with_limit, write_file_atomic,
+    write_file_atomic_with_backups,
+};
+pub use self::pool::{BufferPool, PooledBuffer};
+pub use self::record_file::{RecordFile, RecordIter, SyncPolicy, RECORD_HEADER_LEN};
+pub use self::sealed::{
+    deserialise_sealed, deserialise_sealed_with_aad, serialise_sealed, serialise_sealed_with_aad,
+    SEALING_KEY_LEN, SEALING_NONCE_LEN,
+};
+pub use self::sequence::{
+    deserialise_iter, deserialise_iter_with_limit, serialise_iter, serialise_iter_with_limit,
+    DeserialiseIter,
+};
+pub use self::signed::{
+    derive_public_key, deserialise_verified, deserialise_verified_detached, serialise_signed,
+    serialise_signed_detached, PUBLIC_KEY_LEN, SECRET_KEY_LEN, SIGNATURE_LEN,
+};
+pub use self::tagged::{
+    deserialise_tagged, deserialise_tagged_with_limit, serialise_tagged,
+    serialise_tagged_with_limit, tagged_type, type_fingerprint, Tagged, TYPE_TAG_LEN,
+};
+pub use self::tracked::ErrorContext;
+pub use self::versioned::{
+    deserialise_versioned, deserialise_versioned_with_limit, serialise_versioned,
+    serialise_versioned_with_limit, versioned_schema, Migrations, Versioned, VERSIONED_MAGIC,
+};
+
+#[cfg(feature = "async")]
+mod async_io;
+mod canonical;
+mod checked;
+mod compressed;
+mod format;
+mod frame;
+mod inspect;
+mod limits;
+mod persist;
+mod pool;
+mod reader;
+mod record_file;
+mod sealed;
+mod sequence;
+mod signed;
+mod tagged;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+mod tracked;
+mod versioned;
+
+use self::limits::UNBOUNDED;
+use self::reader::{CountingReader, SliceReader};
+use self::tracked::{top_level_context, Limiter, TrackedSeed, Tracker};
+use bincode::read_types::IoReadReader;
+use bincode::{
+    serialize, serialize_into, serialized_size, serialized_size_bounded, Bounded, Deserializer,
+    ErrorKind, Infinite, SizeLimit,
+};
+use serde::de::{Deserialize, DeserializeOwned, DeserializeSeed};
+use serde::ser::Serialize;
+use std::any;
+use std::cell::Cell;
+use std::io::{Read, Write};
+use std::marker::PhantomData;
+
+quick_error! {
+    /// Serialisation error.
+    #[derive(Debug)]
+    pub enum SerialisationError {
+        /// Error during serialisation (encoding).
+        Serialise(err: ErrorKind) {
+            display("Serialise error: {}", err)
+            source(err)
+        }
+
+        /// Bincode error during deserialisation (decoding), with where in the input it occurred if
+        /// known.
+        Deserialise(err: ErrorKind, context: Option<ErrorContext>) {
+            display("Deserialise error: {}{}", err, match *context {
+                Some(ref context) => format!(" (at {})", context),
+                None => String::new(),
+            })
+            source(err)
+        }
+
+        /// Not all input bytes were consumed when deserialising (decoding).
+        DeserialiseExtraBytes {
+            display("Deserialise error: Not all bytes of slice consumed")
+        }
+
+        /// Checksum of the serialised data does not match its contents.
+        ChecksumMismatch {
+            display("Deserialise error: Checksum mismatch")
+        }
+
+        /// Sealed data could not be decrypted or failed authentication.
+        Decryption {
+            display("Deserialise error: Decryption or authentication failed")
+        }
+
+        /// Signature of signed data is malformed or does not match the public key.
+        InvalidSignature {
+            display("Deserialise error: Invalid signature")
+        }
+
+        /// Frame length exceeds the maximum frame size (length, maximum).
+        FrameTooLarge(len: u64, max: u64) {
+            display("Frame error: Frame of {} bytes exceeds maximum of {} bytes", len, max)
+        }
+
+        /// Stream ended part-way through a frame (bytes received, bytes expected).
+        TruncatedFrame(received: u64, expected: u64) {
+            display("Frame error: Stream ended after {} of {} frame bytes", received, expected)
+        }
+
+        /// A record other than the last in a record file is damaged (byte offset of the record).
+        CorruptRecord(offset: u64) {
+            display("Record error: Corrupt record at byte {}", offset)
+        }
+
+        /// Input does not start with a valid versioned envelope header.
+        MissingVersionHeader {
+            display("Deserialise error: Missing or invalid version header")
+        }
+
+        /// Versioned envelope has a schema version with no registered upgrade (found, current).
+        UnsupportedVersion(found: u32, current: u32) {
+            display("Deserialise error: Unsupported schema version {} (current is {})",
+                    found, current)
+        }
+
+        /// Tagged data was written as a different type (expected tag, found tag).
+        TypeMismatch(expected: u64, found: u64) {
+            display("Deserialise error: Type tag mismatch (expected {:016x}, found {:016x})",
+                    expected, found)
+        }
+
+        /// No fingerprint could be derived from the type's shape.
+        TypeFingerprint(reason: String) {
+            display("Type error: Cannot fingerprint type: {}", reason)
+        }
+
+        /// A sequence or map is longer than the maximum collection length (length, maximum).
+        CollectionTooLong(len: u64, max: u64, context: Option<ErrorContext>) {
+            display("Deserialise error: Collection of {} elements exceeds maximum of {}{}", len, max,
+                    match *context {
+                        Some(ref context) => format!(" (at {})", context),
+                        None => String::new(),
+                    })
+        }
+
+        /// Values are nested deeper than the maximum depth.
+        NestingTooDeep(max: usize, context: Option<ErrorContext>) {
+            display("Deserialise error: Nesting exceeds maximum depth of {}{}", max,
+                    match *context {
+                        Some(ref context) => format!(" (at {})", context),
+                        None => String::new(),
+                    })
+        }
+    }
+}
+
+/// Serialise an `Serialize` type with no limit on the size of the serialised data.
+pub fn serialise<T>(data: &T) -> Result<Vec<u8>, SerialisationError>
+where
+    T: Serialize,
+{
+    Bincode::serialise(data, None)
+}
+
+/// Serialise an `Serialize` type with max limit specified.
+pub fn serialise_with_limit<T>(data: &T, size_limit: Bounded) -> Result<Vec<u8>, SerialisationError>
+where
+    T: Serialize,
+{
+    Bincode::serialise(data, Some(size_limit))
+}
+
+/// Deserialise a `Deserialize` type with the process-wide [default
+/// limits](fn.set_default_limits.html).
+pub fn deserialise<T>(data: &[u8]) -> Result<T, SerialisationError>
+where
+    T: DeserializeOwned,
+{
+    Bincode::deserialise(data, None)
+}
+
+/// Deserialise a `Deserialize` type with max size limit specified.
+///
+/// The default collection length and nesting depth limits still apply.
+pub fn deserialise_with_limit<T>(data: &[u8], size_limit: Bounded) -> Result<T, SerialisationError>
+where
+    T: DeserializeOwned,
+{
+    Bincode::deserialise(data, Some(size_limit))
+}
+
+/// Deserialise a `Deserialize` type with no limits at all.
+///
+/// This should only be used with trusted input, such as data this process wrote itself.
+pub fn deserialise_unbounded<T>(data: &[u8]) -> Result<T, SerialisationError>
+where
+    T: DeserializeOwned,
+{
+    deserialise_slice(data, Infinite, &UNBOUNDED)
+}
+
+/// Deserialise a `Deserialize` type which may borrow `&str` and `&[u8]` fields from `data`, with
+/// the process-wide [default limits](fn.set_default_limits.html).
+pub fn deserialise_borrowed<'a, T>(data: &'a [u8]) -> Result<T, SerialisationError>
+where
+    T: Deserialize<'a>,
+{
+    let limits = default_limits();
+    deserialise_slice(data, Bounded(limits.max_bytes), &limits)
+}
+
+/// Deserialise a `Deserialize` type which may borrow `&str` and `&[u8]` fields from `data`, with
+/// max size limit specified.
+pub fn deserialise_borrowed_with_limit<'a, T>(
+    data: &'a [u8],
+    size_limit: Bounded,
+) -> Result<T, SerialisationError>
+where
+    T: Deserialize<'a>,
+{
+    deserialise_slice(data, size_limit, &default_limits())
+}
+
+/// Deserialise a `Deserialize` type from the start of `data` with the process-wide [default
+/// limits](fn.set_default_limits.html), returning it along with the bytes which follow it.
+pub fn deserialise_prefix<'a, T>(data: &'a [u8]) -> Result<(T, &'a [u8]), SerialisationError>
+where
+    T: Deserialize<'a>,
+{
+    let limits = default_limits();
+    let (value, used) = deserialise_slice_prefix(data, Bounded(limits.max_bytes), &limits)?;
+    Ok((value, &data[used..]))
+}
+
+/// Deserialise a `Deserialize` type from the start of `data` with max size limit specified,
+/// returning it along with the bytes which follow it.
+pub fn deserialise_prefix_with_limit<'a, T>(
+    data: &'a [u8],
+    size_limit: Bounded,
+) -> Result<(T, &'a [u8]), SerialisationError>
+where
+    T: Deserialize<'a>,
+{
+    let (value, used) = deserialise_slice_prefix(data, size_limit, &default_limits())?;
+    Ok((value, &data[used..]))
+}
+
+/// Deserialise every value in a concatenation of serialised values, such as that written by
+/// repeated calls to [`serialise_into()`](fn.serialise_into.html), with the process-wide [default
+/// limits](fn.set_default_limits.html) applied to each value.
+pub fn deserialise_many<'a, T>(data: &'a [u8]) -> Result<Vec<T>, SerialisationError>
+where
+    T: Deserialize<'a>,
+{
+    deserialise_many_with_limit(data, Bounded(default_limits().max_bytes))
+}
+
+/// Deserialise every value in a concatenation of serialised values with max size limit specified
+/// for each value.
+pub fn deserialise_many_with_limit<'a, T>(
+    mut data: &'a [u8],
+    size_limit: Bounded,
+) -> Result<Vec<T>, SerialisationError>
+where
+    T: Deserialize<'a>,
+{
+    let limits = default_limits();
+    let mut values = Vec::new();
+    while !data.is_empty() {
+        let (value, used) = deserialise_slice_prefix(data, size_limit, &limits)?;
+        values.push(value);
+        data = &data[used..];
+    }
+    Ok(values)
+}
+
+/// Serialise an `Serialize` type directly into a `Write` with no limit on the size of the
+/// serialised data.
+pub fn serialise_into<T: Serialize, W: Write>(
+    data: &T,
+    write: &mut W,
+) -> Result<(), SerialisationError> {
+    serialize_into(write, data, Infinite).map_err(|e| SerialisationError::Serialise(*e))
+}
+
+/// Serialise an `Serialize` type directly into a `Write` with max size limit specified.
+pub fn serialise_into_with_limit<T: Serialize, W: Write>(
+    data: &T,
+    write: &mut W,
+    size_limit: Bounded,
+) -> Result<(), SerialisationError> {
+    serialize_into(write, data, size_limit).map_err(|e| SerialisationError::Serialise(*e))
+}
+
+/// Serialise an `Serialize` type into `buffer`, replacing its contents, with no limit on the size
+/// of the serialised data.
+///
+/// The buffer's existing capacity is reused, and it is grown at most once, to exactly the
+/// [serialised size](fn.serialised_size.html) of `data`.
+pub fn serialise_into_vec<T: Serialize>(
+    data: &T,
+    buffer: &mut Vec<u8>,
+) -> Result<(), SerialisationError> {
+    buffer.clear();
+    buffer.reserve(serialised_size(data) as usize);
+    serialise_into(data, buffer)
+}
+
+/// Serialise an `Serialize` type into `buffer`, replacing its contents, with max size limit
+/// specified.
+///
+/// If the limit is exceeded, `buffer` is left empty.
+pub fn serialise_into_vec_with_limit<T: Serialize>(
+    data: &T,
+    buffer: &mut Vec<u8>,
+    size_limit: Bounded,
+) -> Result<(), SerialisationError> {
+    buffer.clear();
+    let size = serialised_size_with_limit(data, size_limit.0)
+        .ok_or(SerialisationError::Serialise(ErrorKind::SizeLimit))?;
+    buffer.reserve(size as usize);
+    serialise_into(data, buffer)
+}
+
+/// Deserialise a `Deserialize` type directly from a `Read` with the process-wide [default
+/// limits](fn.set_default_limits.html).
+pub fn deserialise_from<R: Read, T: DeserializeOwned>(
+    read: &mut R,
+) -> Result<T, SerialisationError> {
+    let limits = default_limits();
+    deserialise_stream(read, Bounded(limits.max_bytes), &limits)
+}
+
+/// Deserialise a `Deserialize` type directly from a `Read` with max size limit specified.
+pub fn deserialise_from_with_limit<R: Read, T: DeserializeOwned>(
+    read: &mut R,
+    size_limit: Bounded,
+) -> Result<T, SerialisationError> {
+    deserialise_stream(read, size_limit, &default_limits())
+}
+
+/// Returns the size that an object would be if serialised using [`serialise()`](fn.serialise.html).
+pub fn serialised_size<T: Serialize>(data: &T) -> u64 {
+    serialized_size(data)
+}
+
+/// Given a maximum size limit, check how large an object would be if it were to be serialised.
+///
+/// If it can be encoded in `max` or fewer bytes, that number will be returned inside `Some`.  If it
+/// goes over bounds, then `None` is returned.
+pub fn serialised_size_with_limit<T: Serialize>(data: &T, max: u64) -> Option<u64> {
+    serialized_size_bounded(data, max)
+}
+
+// Decodes a value from `data`, returning `DeserialiseExtraBytes` if any input is left over.
+fn deserialise_slice<'a, T, S>(
+    data: &'a [u8],
+    size_limit: S,
+    limits: &DeserialiseLimits,
+) -> Result<T, SerialisationError>
+where
+    T: Deserialize<'a>,
+    S: SizeLimit + Copy,
+{
+    let (value, used) = deserialise_slice_prefix(data, size_limit, limits)?;
+    if used != data.len() {
+        return Err(SerialisationError::DeserialiseExtraBytes);
+    }
+    Ok(value)
+}
+
+// Decodes a value from the start of `data`, returning it along with the number of bytes used.
+//
+// The value is decoded enforcing only the limits.  If that fails, it is decoded again recording
+// the offset, type and field path, so that tracking costs nothing unless there is an error.
+fn deserialise_slice_prefix<'a, T, S>(
+    data: &'a [u8],
+    size_limit: S,
+    limits: &DeserialiseLimits,
+) -> Result<(T, usize), SerialisationError>
+where
+    T: Deserialize<'a>,
+    S: SizeLimit + Copy,
+{
+    let reader = SliceReader::new(data);
+    let limiter = Limiter::new(limits);
+    let result = TrackedSeed::new(PhantomData::<T>, &limiter)
+        .deserialize(&mut Deserializer::new(&reader, size_limit));
+    match result {
+        Ok(value) => Ok((value, reader.position())),
+        Err(error) => {
+            let reader = SliceReader::new(data);
+            let position = || reader.position();
+            let tracker = Tracker::new(&position, limits);
+            let retried = TrackedSeed::new(PhantomData::<T>, &tracker)
+                .deserialize(&mut Deserializer::new(&reader, size_limit));
+            match retried {
+                Err(retried_error) => Err(tracker.into_error(*retried_error)),
+                Ok(_) => Err(limiter.into_error(*error, None)),
+            }
+        }
+    }
+}
+
+// Decodes a value from `read`, enforcing the limits.  The input can't be decoded again to find the
+// field path of an error, so its context only gives the offset and the type being decoded.
+fn deserialise_stream<R, T, S>(
+    read: &mut R,
+    size_limit: S,
+    limits: &DeserialiseLimits,
+) -> Result<T, SerialisationError>
+where
+    R: Read,
+    T: DeserializeOwned,
+    S: SizeLimit,
+{
+    let count = Cell::new(0);
+    let reader = IoReadReader::new(CountingReader::new(read, &count));
+    let limiter = Limiter::new(limits);
+    let result = TrackedSeed::new(PhantomData::<T>, &limiter)
+        .deserialize(&mut Deserializer::new(reader, size_limit));
+    result.map_err(|error| {
+        let context = top_level_context(count.get(), any::type_name::<T>());
+        limiter.into_error(*error, Some(context))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bincode::{Bounded, ErrorKind};
+    use serde::de::{self, Visitor};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::fmt;
+    use std::io::Cursor;
+
+    #[test]
+    fn serialise_deserialise() {
+        let original_data = (
+            vec![0u8, 1, 3, 9],
+            vec![-1i64, 888, -8765],
+            "SomeString".to_string(),
+        );
+
+        let serialised_data = unwrap!(serialise(&original_data));
+        let deserialised_data: (Vec<u8>, Vec<i64>, String) = unwrap!(deserialise(&serialised_data));
+        assert_eq!(original_data, deserialised_data);
+
+        // Try to parse a `String` into a `u64` to check the unused bytes triggers an error.
+        let serialised_string = unwrap!(serialise(&"Another string".to_string()));
+        match unwrap_err!(deserialise::<u64>(&serialised_string)) {
+            SerialisationError::DeserialiseExtraBytes => (),
+            err => panic!("{:?}", err),
+        }
+    }
+
+    #[test]
+    fn deserialise_borrowed_fields() {
+        let original_data = (7u32, "SomeString".to_string(), vec![0u8, 1, 3, 9]);
+        let serialised_data = unwrap!(serialise(&original_data));
+
+        let (number, string, bytes): (u32, &str, &[u8]) =
+            unwrap!(deserialise_borrowed(&serialised_data));
+        assert_eq!(number, original_data.0);
+        assert_eq!(string, original_data.1);
+        assert_eq!(bytes, &original_data.2[..]);
+        // The string and bytes point into the serialised buffer rather than being copied.
+        let input = serialised_data.as_ptr_range();
+        assert!(input.contains(&string.as_ptr()));
+        assert!(input.contains(&bytes.as_ptr()));
+
+        let (_, string, _): (u32, &str, &[u8]) = unwrap!(deserialise_borrowed_with_limit(
+            &serialised_data,
+            Bounded(serialised_data.len() as u64)
+        ));
+        assert_eq!(string, original_data.1);
+
+        let mut extended = serialised_data.clone();
+        extended.push(0);
+        match unwrap_err!(deserialise_borrowed::<(u32, &str, &[u8])>(&extended)) {
+            SerialisationError::DeserialiseExtraBytes => (),
+            err => panic!("{:?}", err),
+        }
+        match unwrap_err!(deserialise_borrowed::<(u32, &str, &[u8])>(
+            &serialised_data[..serialised_data.len() - 1]
+        )) {
+            SerialisationError::Deserialise(ErrorKind::IoError(_), _) => (),
+            err => panic!("{:?}", err),
+        }
+    }
+
+    #[derive(serde_derive::Serialize, serde_derive::Deserialize, Debug)]
+    struct Message {
+        id: u32,
+        body: Body,
+    }
+
+    #[derive(serde_derive::Serialize, serde_derive::Deserialize, Debug)]
+    enum Body {
+        Ping,
+        Entries { entries: Vec<Entry> },
+    }
+
+    #[derive(serde_derive::Serialize, serde_derive::Deserialize, Debug)]
+    struct Entry {
+        id: u8,
+        name: String,
+    }
+
+    #[test]
+    fn error_context() {
+        let message = Message {
+            id: 1,
+            body: Body::Entries {
+                entries: ["a", "bb", "ccc", "dddd"]
+                    .iter()
+                    .enumerate()
+                    .map(|(id, name)| Entry {
+                        id: id as u8,
+                        name: name.to_string(),
+                    })
+                    .collect(),
+            },
+        };
+        let mut serialised = unwrap!(serialise(&message));
+        // Make the last entry's name invalid UTF-8.
+        let last = serialised.len() - 1;
+        serialised[last] = 0xff;
+
+        let check = |err: SerialisationError| {
+            let context = match err {
+                SerialisationError::Deserialise(ErrorKind::InvalidEncoding { .. }, ref context) => {
+                    unwrap!(context.clone())
+                }
+                ref err => panic!("{:?}", err),
+            };
+            assert_eq!(context.offset(), serialised.len() as u64);
+            assert_eq!(context.type_name(), "alloc::string::String");
+            assert_eq!(context.path(), "Message.body::Entries.entries[3].name");
+            assert!(err.to_string().contains(&format!(
+                "at byte {} while decoding `alloc::string::String` at \
+                 `Message.body::Entries.entries[3].name`",
+                serialised.len()
+            )));
+            let source = unwrap!(std::error::Error::source(&err));
+            assert!(source.downcast_ref::<ErrorKind>().is_some());
+        };
+        check(unwrap_err!(deserialise::<Message>(&serialised)));
+
+        // A stream can't be decoded again to find the path, so only the offset and the top-level
+        // type are given.
+        match unwrap_err!(deserialise_from::<_, Message>(&mut Cursor::new(
+            &serialised
+        ))) {
+            SerialisationError::Deserialise(ErrorKind::InvalidEncoding { .. }, Some(context)) => {
+                assert_eq!(context.offset(), serialised.len() as u64);
+                assert!(context.type_name().ends_with("Message"));
+                assert_eq!(context.path(), "");
+            }
+            err => panic!("{:?}", err),
+        }
+
+        // Truncated input fails while reading the last entry's name.
+        match unwrap_err!(deserialise::<Message>(&serialised[..last])) {
+            SerialisationError::Deserialise(ErrorKind::IoError(_), Some(context)) => {
+                assert_eq!(context.path(), "Message.body::Entries.entries[3].name");
+            }
+            err => panic!("{:?}", err),
+        }
+
+        // An out-of-range variant index is reported against the enum.
+        let mut bad_variant = unwrap!(serialise(&Message {
+            id: 1,
+            body: Body::Ping,
+        }));
+        bad_variant[4] = 9;
+        match unwrap_err!(deserialise::<Message>(&bad_variant)) {
+            SerialisationError::Deserialise(_, Some(context)) => {
+                assert_eq!(context.offset(), 8);
+                assert!(context.type_name().ends_with("Body"));
+                assert_eq!(context.path(), "Message.body");
+            }
+            err => panic!("{:?}", err),
+        }
+    }
+
+    #[derive(serde_derive::Serialize, serde_derive::Deserialize, Debug)]
+    enum Nested {
+        Leaf,
+        Node(Box<Nested>),
+    }
+
+    #[test]
+    fn deserialise_limits() {
+        let limits = DeserialiseLimits::default();
+
+        let mut nested = Nested::Leaf;
+        for _ in 1..limits.max_depth {
+            nested = Nested::Node(Box::new(nested));
+        }
+        let _ = unwrap!(deserialise::<Nested>(&unwrap!(serialise(&nested))));
+        nested = Nested::Node(Box::new(nested));
+        let serialised = unwrap!(serialise(&nested));
+        match unwrap_err!(deserialise::<Nested>(&serialised)) {
+            SerialisationError::NestingTooDeep(max, Some(context)) => {
+                assert_eq!(max, limits.max_depth);
+                assert!(context.path().starts_with("Nested::Node::Node"));
+            }
+            err => panic!("{:?}", err),
+        }
+        let _ = unwrap!(deserialise_unbounded::<Nested>(&serialised));
+
+        // Zero-sized elements take no space, so only the collection length limit catches these.
+        let units = vec![(); limits.max_collection_len as usize + 1];
+        let serialised = unwrap!(serialise(&units));
+        assert_eq!(serialised.len(), 8);
+        match unwrap_err!(deserialise::<Vec<()>>(&serialised)) {
+            SerialisationError::CollectionTooLong(len, max, _) => {
+                assert_eq!(len, units.len() as u64);
+                assert_eq!(max, limits.max_collection_len);
+            }
+            err => panic!("{:?}", err),
+        }
+        match unwrap_err!(deserialise_with_limit::<Vec<()>>(&serialised, Bounded(8))) {
+            SerialisationError::CollectionTooLong(..) => (),
+            err => panic!("{:?}", err),
+        }
+        assert_eq!(
+            units,
+            unwrap!(deserialise_unbounded::<Vec<()>>(&serialised))
+        );
+
+        // A hostile length prefix fails before anything is allocated for it.
+        match unwrap_err!(deserialise_from::<_, String>(&mut Cursor::new([0xff; 16]))) {
+            SerialisationError::Deserialise(ErrorKind::SizeLimit, _) => (),
+            err => panic!("{:?}", err),
+        }
+    }
+
+    #[test]
+    fn deserialise_prefix_and_many() {
+        let header = (3u8, "dispatch".to_string());
+        let body = vec![-1i64, 888, -8765];
+        let mut serialised = unwrap!(serialise(&header));
+        unwrap!(serialise_into(&body, &mut serialised));
+
+        let (decoded, remainder) = unwrap!(deserialise_prefix::<(u8, String)>(&serialised));
+        assert_eq!(decoded, header);
+        assert_eq!(unwrap!(deserialise::<Vec<i64>>(remainder)), body);
+        let (decoded, remainder) = unwrap!(deserialise_prefix::<Vec<i64>>(remainder));
+        assert_eq!(decoded, body);
+        assert!(remainder.is_empty());
+
+        match unwrap_err!(deserialise_prefix_with_limit::<(u8, String)>(
+            &serialised,
+            Bounded(8)
+        )) {
+            SerialisationError::Deserialise(ErrorKind::SizeLimit, _) => (),
+            err => panic!("{:?}", err),
+        }
+
+        let values = [9u16, 0, 65535];
+        let mut serialised = vec![];
+        for value in &values {
+            unwrap!(serialise_into(value, &mut serialised));
+        }
+        assert_eq!(unwrap!(deserialise_many::<u16>(&serialised)), values);
+        assert!(unwrap!(deserialise_many::<u16>(&[])).is_empty());
+
+        // A truncated final value is an error, not silently dropped.
+        match unwrap_err!(deserialise_many::<u16>(&serialised[..5])) {
+            SerialisationError::Deserialise(ErrorKind::IoError(_), _) => (),
+            err => panic!("{:?}", err),
+        }
+
+        // Borrowed values, each limited in size.
+        let mut serialised = unwrap!(serialise(&"short"));
+        unwrap!(serialise_into(&"much longer", &mut serialised));
+        assert_eq!(
+            unwrap!(deserialise_many_with_limit::<&str>(
+                &serialised,
+                Bounded(19)
+            )),
+            vec!["short", "much longer"]
+        );
+        match unwrap_err!(deserialise_many_with_limit::<&str>(
+            &serialised,
+            Bounded(18)
+        )) {
+            SerialisationError::Deserialise(ErrorKind::SizeLimit, _) => (),
+            err => panic!("{:?}", err),
+        }
+    }
+
+    #[test]
+    fn serialise_into_deserialise_from() {
+        let original_data = (
+            vec![0u8, 1, 3, 9],
+            vec![-1i64, 888, -8765],
+            "SomeString".to_string(),
+        );
+        let mut serialised_data = vec![];
+        unwrap!(serialise_into(&original_data, &mut serialised_data));
+
+        let mut serialised = Cursor::new(serialised_data);
+        let deserialised_data: (Vec<u8>, Vec<i64>, String) =
+            unwrap!(deserialise_from(&mut serialised));
+        assert_eq!(original_data, deserialised_data);
+    }
+
+    #[test]
+    fn serialise_into_vec_reuses_buffer() {
+        let first = vec![-1i64, 888, -8765];
+        let second = (7u8, "SomeString".to_string());
+
+        let mut buffer = Vec::with_capacity(64);
+        let address = buffer.as_ptr();
+        unwrap!(serialise_into_vec(&first, &mut buffer));
+        assert_eq!(buffer, unwrap!(serialise(&first)));
+        unwrap!(serialise_into_vec(&second, &mut buffer));
+        assert_eq!(buffer, unwrap!(serialise(&second)));
+        assert_eq!(buffer.as_ptr(), address);
+
+        // An empty buffer is allocated exactly once, at the serialised size.
+        let mut buffer = Vec::new();
+        unwrap!(serialise_into_vec_with_limit(
+            &first,
+            &mut buffer,
+            Bounded(32)
+        ));
+        assert_eq!(buffer.capacity(), buffer.len());
+
+        match unwrap_err!(serialise_into_vec_with_limit(
+            &first,
+            &mut buffer,
+            Bounded(16)
+        )) {
+            SerialisationError::Serialise(ErrorKind::SizeLimit) => (),
+            err => panic!("{:?}", err),
+        }
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn upper_limit() {
+        let upper_limit = Bounded(64);
+        // Test with data which is at limit
+        let mut original_data = (1u64..8).collect::<Vec<_>>();
+        let mut serialised_data = unwrap!(serialise_with_limit(&original_data, upper_limit));
+        let mut deserialised_data: Vec<u64> = unwrap!(deserialise(&serialised_data));
+        assert_eq!(original_data, deserialised_data);
+
+        serialised_data.clear();
+        unwrap!(serialise_into_with_limit(
+            &original_data,
+            &mut serialised_data,
+            upper_limit,
+        ));
+        let mut serialised = Cursor::new(serialised_data);
+        deserialised_data = unwrap!(deserialise_from(&mut serialised));
+        assert_eq!(original_data, deserialised_data);
+
+        // Try to serialise data above limit
+        original_data.push(0);
+        if let Err(SerialisationError::Serialise(ErrorKind::SizeLimit)) =
+            serialise_with_limit(&original_data, upper_limit)
+        {
+        } else {
+            panic!("Expected size limit error.");
+        }
+        let mut buffer = vec![];
+        if let Err(SerialisationError::Serialise(ErrorKind::SizeLimit)) =
+            serialise_into_with_limit(&original_data, &mut buffer, upper_limit)
+        {
+        } else {
+            panic!("Expected size limit error.");
+        }
+
+        // Try to deserialise data above limit
+        let excessive = unwrap!(serialise(&original_data));
+        if let Err(SerialisationError::Deserialise(ErrorKind::SizeLimit, _)) =
+            deserialise_with_limit::<Vec<u64>>(&excessive, upper_limit)
+        {
+        } else {
+            panic!("Expected size limit error.");
+        }
+        serialised = Cursor::new(excessive);
+        if let Err(SerialisationError::Deserialise(ErrorKind::SizeLimit, _)) =
+            deserialise_from_with_limit::<Cursor<_>, Vec<u64>>(&mut serialised, upper_limit)
+        {
+        } else {
+            panic!("Expected size limit error.");
+        }
+    }
+
+    #[test]
+    fn sizes() {
+        let data = (1u64..8).collect::<Vec<_>>();
+        assert_eq!(serialised_size(&data), 64);
+        assert_eq!(serialised_size_with_limit(&data, 100), Some(64));
+        assert_eq!(serialised_size_with_limit(&data, 64), Some(64));
+        assert!(serialised_size_with_limit(&data, 63).is_none());
+    }
+
+    #[derive(PartialEq, Eq, Debug)]
+    struct Wrapper([u8; 1]);
+
+    impl Serialize for Wrapper {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_bytes(&self.0[..])
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Wrapper {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Wrapper, D::Error> {
+            struct WrapperVisitor;
+            impl<'de> Visitor<'de> for WrapperVisitor {
+                type Value = Wrapper;
+                fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                    write!(formatter, "Wrapper")
+                }
+                fn visit_bytes<E: de::Error>(self, value: &[u8]) -> Result<Self::Value, E> {
+                    if value.len() != 1 {
+                        return Err(de::Error::invalid_length(value.len(), &self));
+                    }
+                    Ok(Wrapper([value[0]]))
+                }
+            }
+            deserializer.deserialize_bytes(WrapperVisitor)
+        }
+    }
+
+    #[test]
+    // The bincode implementation of `serialize_bytes` puts the number of bytes of raw data as the
+    // first 8 bytes of the encoded data.  The corresponding `deserialize_bytes` uses these first 8
+    // bytes to deduce the size of the buffer into which the raw bytes should then be copied.  If we
+    // use bincode's `deserialize_from(.., Infinite)` to try and parse such data, size-checking is
+    // disabled when allocating the buffer, and corrupted serialised data could cause an OOM crash.
+    fn deserialize_bytes() {
+        let wrapper = Wrapper([255]);
+        let serialised_wrapper = unwrap!(serialise(&wrapper));
+        // If the following assertion fails, revisit how we're encoding data via `serialize_bytes`
+        // to check that the following `tampered` array below is still trying to trigger an OOM
+        // error.
+        assert_eq!(serialised_wrapper, [1, 0, 0, 0, 0, 0, 0, 0, 255]);
+        let deserialised_wrapper: Wrapper = unwrap!(deserialise(&serialised_wrapper));
+        assert_eq!(wrapper, deserialised_wrapper);
+
+        // Try to trigger an OOM crash.
+        let tampered = [255u8; 9];
+        match unwrap_err!(deserialise::<Wrapper>(&tampered)) {
+            SerialisationError::Deserialise(..) => (),
+            err => panic!("{:?}", err),
+        }
+
+        match unwrap_err!(deserialise::<Wrapper>(&[1, 0, 0, 0, 0, 0, 0, 0, 255, 255])) {
+            SerialisationError::DeserialiseExtraBytes => (),
+            err => panic!("{:?}", err),
+        }
+    }
+}
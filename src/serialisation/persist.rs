@@ -0,0 +1,281 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// https://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+use super::checked::verify_checksum;
+use super::limits::default_limits;
+use super::{deserialise_checked_with_limit, serialise_checked, SerialisationError, CHECKSUM_LEN};
+use bincode::{Bounded, ErrorKind};
+use serde::de::DeserializeOwned;
+use serde::ser::Serialize;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Atomically replace the file at `path` with `data`, serialised with a trailing CRC32C checksum.
+///
+/// The data is written to a temporary file alongside `path`, flushed to disk and then renamed over
+/// `path`, so that after a crash the file holds either the old or the new value in full.
+pub fn write_file_atomic<T: Serialize, P: AsRef<Path>>(
+    path: P,
+    data: &T,
+) -> Result<(), SerialisationError> {
+    write_file_atomic_with_backups(path, data, 0)
+}
+
+/// Like [`write_file_atomic()`](fn.write_file_atomic.html), but first keeps up to `backups`
+/// previous generations of the file, as `<path>.1` (the most recent) to `<path>.<backups>`.
+///
+/// The current file is only kept if its checksum is correct; otherwise the backups are left as
+/// they are, so that a corrupt file never displaces a good one.
+///
+/// These are used by [`read_file_with_backups()`](fn.read_file_with_backups.html) if the latest
+/// file is missing or corrupt.
+pub fn write_file_atomic_with_backups<T: Serialize, P: AsRef<Path>>(
+    path: P,
+    data: &T,
+    backups: usize,
+) -> Result<(), SerialisationError> {
+    let path = path.as_ref();
+    let serialised = serialise_checked(data)?;
+    let temp_path = temp_path(path);
+    let result = write_and_sync(&temp_path, &serialised)
+        .and_then(|()| {
+            if backups > 0 {
+                back_up(path, backups)
+            } else {
+                Ok(())
+            }
+        })
+        .and_then(|()| fs::rename(&temp_path, path));
+    if let Err(error) = result {
+        let _ = fs::remove_file(&temp_path);
+        return Err(write_error(error));
+    }
+    sync_parent_dir(path).map_err(write_error)
+}
+
+/// Read a value written by [`write_file_atomic()`](fn.write_file_atomic.html) with the
+/// process-wide [default limits](fn.set_default_limits.html).
+pub fn read_file<T: DeserializeOwned, P: AsRef<Path>>(path: P) -> Result<T, SerialisationError> {
+    read_file_with_limit(path, Bounded(default_limits().max_bytes))
+}
+
+/// Read a value written by [`write_file_atomic()`](fn.write_file_atomic.html) with max size limit
+/// specified.
+///
+/// The limit applies to the payload and excludes the checksum.  Files larger than the limit are
+/// rejected without being read.
+pub fn read_file_with_limit<T: DeserializeOwned, P: AsRef<Path>>(
+    path: P,
+    size_limit: Bounded,
+) -> Result<T, SerialisationError> {
+    let mut file = File::open(path).map_err(read_error)?;
+    let len = file.metadata().map_err(read_error)?.len();
+    if len > size_limit.0.saturating_add(CHECKSUM_LEN as u64) {
+        return Err(SerialisationError::Deserialise(ErrorKind::SizeLimit, None));
+    }
+    let mut serialised = Vec::with_capacity(len as usize);
+    let _ = file.read_to_end(&mut serialised).map_err(read_error)?;
+    deserialise_checked_with_limit(&serialised, size_limit)
+}
+
+/// Read a value written by
+/// [`write_file_atomic_with_backups()`](fn.write_file_atomic_with_backups.html) with the
+/// process-wide [default limits](fn.set_default_limits.html), falling back to the most recent of up
+/// to `backups` previous generations which can be read.
+///
+/// If no generation can be read, the error from reading the latest file is returned.
+pub fn read_file_with_backups<T: DeserializeOwned, P: AsRef<Path>>(
+    path: P,
+    backups: usize,
+) -> Result<T, SerialisationError> {
+    let path = path.as_ref();
+    let error = match read_file(path) {
+        Ok(value) => return Ok(value),
+        Err(error) => error,
+    };
+    for generation in 1..=backups {
+        if let Ok(value) = read_file(backup_path(path, generation)) {
+            return Ok(value);
+        }
+    }
+    Err(error)
+}
+
+fn write_and_sync(path: &Path, serialised: &[u8]) -> io::Result<()> {
+    let mut file = File::create(path)?;
+    file.write_all(serialised)?;
+    file.sync_all()
+}
+
+// Shifts the backups of `path` along a generation and copies the current file to `<path>.1`,
+// unless the current file is missing or fails its checksum.
+fn back_up(path: &Path, backups: usize) -> io::Result<()> {
+    let current = match fs::read(path) {
+        Ok(current) => current,
+        Err(ref error) if error.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(error) => return Err(error),
+    };
+    if verify_checksum(&current).is_err() {
+        return Ok(());
+    }
+    for generation in (1..backups).rev() {
+        ignore_not_found(fs::rename(
+            backup_path(path, generation),
+            backup_path(path, generation + 1),
+        ))?;
+    }
+    // Copy rather than rename the current file, so that `path` always exists.
+    let temp_path = temp_path(path);
+    let result = write_and_sync(&temp_path, &current)
+        .and_then(|()| fs::rename(&temp_path, backup_path(path, 1)));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+fn ignore_not_found(result: io::Result<()>) -> io::Result<()> {
+    match result {
+        Err(ref error) if error.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+// Makes the renames durable.  Directories can't be opened for syncing on Windows, where `rename`
+// is already durable once it returns.
+#[cfg(unix)]
+fn sync_parent_dir(path: &Path) -> io::Result<()> {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => File::open(dir)?.sync_all(),
+        _ => File::open(".")?.sync_all(),
+    }
+}
+
+#[cfg(not(unix))]
+fn sync_parent_dir(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+// A temporary file alongside `path`, named uniquely so that concurrent writers, in this process or
+// others, don't clobber each other's files.
+fn temp_path(path: &Path) -> PathBuf {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+    suffixed(path, &format!("tmp.{}.{}", process::id(), count))
+}
+
+fn backup_path(path: &Path, generation: usize) -> PathBuf {
+    suffixed(path, &generation.to_string())
+}
+
+fn suffixed(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(".");
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+fn read_error(error: io::Error) -> SerialisationError {
+    SerialisationError::Deserialise(ErrorKind::IoError(error), None)
+}
+
+fn write_error(error: io::Error) -> SerialisationError {
+    SerialisationError::Serialise(ErrorKind::IoError(error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!(
+            "maidsafe_utilities_persist_{}_{}",
+            name,
+            process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        unwrap!(fs::create_dir_all(&dir));
+        dir
+    }
+
+    #[test]
+    fn write_and_read() {
+        let dir = temp_dir("atomic");
+        let path = dir.join("state");
+        let state = (7u64, vec!["peer".to_owned(); 3]);
+        unwrap!(write_file_atomic(&path, &state));
+        assert_eq!(unwrap!(read_file::<(u64, Vec<String>), _>(&path)), state);
+        assert_eq!(unwrap!(fs::read_dir(&dir)).count(), 1);
+        assert_ne!(temp_path(&path), temp_path(&path));
+
+        let state = (8u64, Vec::new());
+        unwrap!(write_file_atomic(&path, &state));
+        assert_eq!(unwrap!(read_file::<(u64, Vec<String>), _>(&path)), state);
+        assert!(!backup_path(&path, 1).exists());
+
+        match unwrap_err!(read_file_with_limit::<(u64, Vec<String>), _>(
+            &path,
+            Bounded(8)
+        )) {
+            SerialisationError::Deserialise(ErrorKind::SizeLimit, None) => (),
+            err => panic!("{:?}", err),
+        }
+        match unwrap_err!(read_file::<u64, _>(dir.join("missing"))) {
+            SerialisationError::Deserialise(ErrorKind::IoError(ref error), None)
+                if error.kind() == io::ErrorKind::NotFound => {}
+            err => panic!("{:?}", err),
+        }
+        unwrap!(fs::remove_dir_all(&dir));
+    }
+
+    #[test]
+    fn backup_rotation() {
+        let dir = temp_dir("backups");
+        let path = dir.join("state");
+        for generation in 0..5u32 {
+            unwrap!(write_file_atomic_with_backups(&path, &generation, 2));
+        }
+        assert_eq!(unwrap!(read_file::<u32, _>(&path)), 4);
+        assert_eq!(unwrap!(read_file::<u32, _>(backup_path(&path, 1))), 3);
+        assert_eq!(unwrap!(read_file::<u32, _>(backup_path(&path, 2))), 2);
+        assert!(!backup_path(&path, 3).exists());
+
+        // A corrupt latest file falls back to the previous generation, and a missing one to the
+        // first which can be read.
+        let mut contents = unwrap!(fs::read(&path));
+        contents[0] ^= 0xff;
+        unwrap!(fs::write(&path, &contents));
+        match unwrap_err!(read_file::<u32, _>(&path)) {
+            SerialisationError::ChecksumMismatch => (),
+            err => panic!("{:?}", err),
+        }
+        assert_eq!(unwrap!(read_file_with_backups::<u32, _>(&path, 2)), 3);
+
+        // Writing over a corrupt file leaves the backups as they were.
+        unwrap!(write_file_atomic_with_backups(&path, &5u32, 2));
+        assert_eq!(unwrap!(read_file::<u32, _>(&path)), 5);
+        assert_eq!(unwrap!(read_file::<u32, _>(backup_path(&path, 1))), 3);
+        assert_eq!(unwrap!(read_file::<u32, _>(backup_path(&path, 2))), 2);
+        assert_eq!(unwrap!(fs::read_dir(&dir)).count(), 3);
+
+        unwrap!(fs::remove_file(&path));
+        unwrap!(fs::write(backup_path(&path, 1), b"torn"));
+        assert_eq!(unwrap!(read_file_with_backups::<u32, _>(&path, 2)), 2);
+        match unwrap_err!(read_file_with_backups::<u32, _>(&path, 1)) {
+            SerialisationError::Deserialise(ErrorKind::IoError(_), None) => (),
+            err => panic!("{:?}", err),
+        }
+        unwrap!(fs::remove_dir_all(&dir));
+    }
+}
@@ -0,0 +1,197 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// https://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+use super::{serialise_into_vec, serialise_into_vec_with_limit, SerialisationError};
+use bincode::Bounded;
+use serde::ser::Serialize;
+use std::mem;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex};
+
+/// A thread-safe pool of reusable serialisation buffers.
+///
+/// Buffers handed out by the pool are returned to it when dropped, keeping their capacity, so that
+/// in steady state serialising a message needs no allocation at all.  Cloning a `BufferPool` gives
+/// another handle to the same pool.
+#[derive(Clone, Debug)]
+pub struct BufferPool {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    buffers: Mutex<Vec<Vec<u8>>>,
+    max_buffers: usize,
+    max_capacity: usize,
+}
+
+impl BufferPool {
+    /// Creates a pool which keeps at most `max_buffers` idle buffers.  Buffers which have grown
+    /// beyond `max_capacity` bytes are freed rather than returned to the pool.
+    pub fn new(max_buffers: usize, max_capacity: usize) -> Self {
+        BufferPool {
+            inner: Arc::new(Inner {
+                buffers: Mutex::new(Vec::with_capacity(max_buffers)),
+                max_buffers,
+                max_capacity,
+            }),
+        }
+    }
+
+    /// Takes an empty buffer from the pool, or allocates a new one if the pool is empty.
+    pub fn get(&self) -> PooledBuffer {
+        let buffer = unwrap!(self.inner.buffers.lock()).pop().unwrap_or_default();
+        PooledBuffer {
+            buffer,
+            pool: Arc::clone(&self.inner),
+        }
+    }
+
+    /// Serialises `data` into a buffer taken from the pool, with no limit on the size of the
+    /// serialised data.
+    pub fn serialise<T: Serialize>(&self, data: &T) -> Result<PooledBuffer, SerialisationError> {
+        let mut buffer = self.get();
+        serialise_into_vec(data, &mut buffer)?;
+        Ok(buffer)
+    }
+
+    /// Serialises `data` into a buffer taken from the pool, with max size limit specified.
+    pub fn serialise_with_limit<T: Serialize>(
+        &self,
+        data: &T,
+        size_limit: Bounded,
+    ) -> Result<PooledBuffer, SerialisationError> {
+        let mut buffer = self.get();
+        serialise_into_vec_with_limit(data, &mut buffer, size_limit)?;
+        Ok(buffer)
+    }
+
+    /// Returns the number of idle buffers currently held by the pool.
+    pub fn idle_buffers(&self) -> usize {
+        unwrap!(self.inner.buffers.lock()).len()
+    }
+}
+
+/// A buffer taken from a [`BufferPool`](struct.BufferPool.html), which is returned to the pool
+/// when dropped.
+#[derive(Debug)]
+pub struct PooledBuffer {
+    buffer: Vec<u8>,
+    pool: Arc<Inner>,
+}
+
+impl PooledBuffer {
+    /// Detaches the buffer from its pool, so that it won't be returned to it.
+    pub fn into_inner(mut self) -> Vec<u8> {
+        mem::take(&mut self.buffer)
+    }
+}
+
+impl Deref for PooledBuffer {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Vec<u8> {
+        &self.buffer
+    }
+}
+
+impl DerefMut for PooledBuffer {
+    fn deref_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.buffer
+    }
+}
+
+impl AsRef<[u8]> for PooledBuffer {
+    fn as_ref(&self) -> &[u8] {
+        &self.buffer
+    }
+}
+
+impl Drop for PooledBuffer {
+    fn drop(&mut self) {
+        let mut buffer = mem::take(&mut self.buffer);
+        if buffer.capacity() == 0 || buffer.capacity() > self.pool.max_capacity {
+            return;
+        }
+        buffer.clear();
+        let mut buffers = unwrap!(self.pool.buffers.lock());
+        if buffers.len() < self.pool.max_buffers {
+            buffers.push(buffer);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serialisation::{deserialise, serialise};
+    use bincode::ErrorKind;
+    use std::thread;
+
+    #[test]
+    fn buffers_are_reused() {
+        let pool = BufferPool::new(2, 1024);
+        let message = (7u32, "reused".to_owned(), vec![1u64, 2, 3]);
+
+        let serialised = unwrap!(pool.serialise(&message));
+        assert_eq!(*serialised, unwrap!(serialise(&message)));
+        assert_eq!(serialised.capacity(), serialised.len());
+        let address = serialised.as_ptr();
+        drop(serialised);
+        assert_eq!(pool.idle_buffers(), 1);
+
+        // The same allocation is handed out again, cleared.
+        let buffer = pool.get();
+        assert!(buffer.is_empty());
+        assert_eq!(buffer.as_ptr(), address);
+        drop(buffer);
+
+        // Oversized and detached buffers aren't returned to the pool.
+        let _ = pool.get();
+        assert_eq!(pool.idle_buffers(), 1);
+        let large = unwrap!(pool.serialise(&vec![0u8; 2048]));
+        assert_eq!(pool.idle_buffers(), 0);
+        drop(large);
+        assert_eq!(pool.idle_buffers(), 0);
+        let detached = unwrap!(pool.serialise(&message)).into_inner();
+        assert_eq!(
+            unwrap!(deserialise::<(u32, String, Vec<u64>)>(&detached)),
+            message
+        );
+        assert_eq!(pool.idle_buffers(), 0);
+
+        match unwrap_err!(pool.serialise_with_limit(&message, Bounded(8))) {
+            SerialisationError::Serialise(ErrorKind::SizeLimit) => (),
+            err => panic!("{:?}", err),
+        }
+    }
+
+    #[test]
+    fn shared_between_threads() {
+        let pool = BufferPool::new(4, 1024);
+        let threads = (0..4u64)
+            .map(|index| {
+                let pool = pool.clone();
+                thread::spawn(move || {
+                    for value in 0..100 {
+                        let serialised = unwrap!(pool.serialise(&(index, value)));
+                        assert_eq!(
+                            unwrap!(deserialise::<(u64, u64)>(&serialised)),
+                            (index, value)
+                        );
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for thread in threads {
+            unwrap!(thread.join());
+        }
+        assert!(pool.idle_buffers() <= 4);
+    }
+}
@@ -0,0 +1,104 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// https://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+use bincode::read_types::BincodeRead;
+use bincode::ErrorKind;
+use serde::de::Visitor;
+use std::cell::Cell;
+use std::io::{self, Read};
+use std::str;
+
+/// Byte slice reader for bincode which supports borrowing from the input and keeps track of how
+/// many bytes have been consumed.
+///
+/// Bincode reads through a shared reference so that the position can be queried part-way through
+/// decoding.
+pub struct SliceReader<'de> {
+    slice: &'de [u8],
+    position: Cell<usize>,
+}
+
+impl<'de> SliceReader<'de> {
+    pub fn new(slice: &'de [u8]) -> Self {
+        SliceReader {
+            slice,
+            position: Cell::new(0),
+        }
+    }
+
+    /// Number of bytes consumed so far.
+    pub fn position(&self) -> usize {
+        self.position.get()
+    }
+
+    fn consume(&self, length: usize) -> bincode::Result<&'de [u8]> {
+        let position = self.position.get();
+        if length > self.slice.len() - position {
+            return Err(Box::new(ErrorKind::IoError(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "",
+            ))));
+        }
+        self.position.set(position + length);
+        Ok(&self.slice[position..position + length])
+    }
+}
+
+impl<'de> Read for &SliceReader<'de> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        let read = (&self.slice[self.position.get()..]).read(out)?;
+        self.position.set(self.position.get() + read);
+        Ok(read)
+    }
+}
+
+impl<'de> BincodeRead<'de> for &SliceReader<'de> {
+    fn forward_read_str<V>(&mut self, length: usize, visitor: V) -> bincode::Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let string =
+            str::from_utf8(self.consume(length)?).map_err(|_| ErrorKind::InvalidEncoding {
+                desc: "string was not valid utf8",
+                detail: None,
+            })?;
+        visitor.visit_borrowed_str(string)
+    }
+
+    fn get_byte_buffer(&mut self, length: usize) -> bincode::Result<Vec<u8>> {
+        Ok(self.consume(length)?.to_vec())
+    }
+
+    fn forward_read_bytes<V>(&mut self, length: usize, visitor: V) -> bincode::Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_borrowed_bytes(self.consume(length)?)
+    }
+}
+
+/// Wraps a `Read`, counting the bytes read from it into a shared `Cell`.
+pub struct CountingReader<'c, R> {
+    inner: R,
+    count: &'c Cell<usize>,
+}
+
+impl<'c, R: Read> CountingReader<'c, R> {
+    pub fn new(inner: R, count: &'c Cell<usize>) -> Self {
+        CountingReader { inner, count }
+    }
+}
+
+impl<'c, R: Read> Read for CountingReader<'c, R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(out)?;
+        self.count.set(self.count.get() + read);
+        Ok(read)
+    }
+}
@@ -0,0 +1,580 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// https://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+use super::{serialise_into_vec_with_limit, Bincode, Format, SerialisationError};
+use bincode::{Bounded, ErrorKind};
+use crc32c::{crc32c, crc32c_append};
+use serde::de::DeserializeOwned;
+use serde::ser::Serialize;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Length of the header written before every record: the payload's length and a CRC32C checksum
+/// of the length and payload, both little-endian `u32`s.
+pub const RECORD_HEADER_LEN: usize = 8;
+
+// The longest a record can be, and so the most that an interrupted append can leave behind.
+const MAX_RECORD_LEN: u64 = RECORD_HEADER_LEN as u64 + u32::MAX as u64;
+
+// The most records which can claim to end at the end of a damaged tail before the tail is treated
+// as corrupt rather than checksumming them all.
+const MAX_TAIL_CANDIDATES: usize = 16;
+
+/// When a [`RecordFile`](struct.RecordFile.html) asks the operating system to flush appended
+/// records to disk.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SyncPolicy {
+    /// Only when [`sync()`](struct.RecordFile.html#method.sync) is called.
+    Never,
+    /// After every appended record.
+    EveryRecord,
+    /// After every `n` appended records.
+    Every(u64),
+}
+
+/// An append-only file of serialised records, which survives a crash part-way through an append.
+///
+/// Each record is written with a length and checksum header.  When the file is opened, a torn
+/// final record left by an interrupted append is detected and truncated away.
+///
+/// The invalid region after the last valid record is only treated as torn if it is no longer than
+/// a single record and no valid record within it runs to the end of the file.  A damaged record
+/// followed by valid ones, e.g. one whose length has been corrupted, is reported as
+/// `SerialisationError::CorruptRecord` rather than silently dropping the records following it.
+pub struct RecordFile {
+    file: File,
+    path: PathBuf,
+    sync_policy: SyncPolicy,
+    end: u64,
+    len: u64,
+    offsets: Option<Vec<u64>>,
+    reader: Mutex<RecordReader>,
+    unsynced: u64,
+    truncated: u64,
+}
+
+// The handle used by `RecordFile::get()`, with the index of the record at its current position if
+// known, so that reading records in order neither rescans nor seeks.
+struct RecordReader {
+    reader: BufReader<File>,
+    index: Option<u64>,
+    position: u64,
+}
+
+impl RecordFile {
+    /// Opens the record file at `path`, creating it if it doesn't exist and recovering from any
+    /// torn final record.
+    pub fn open<P: AsRef<Path>>(
+        path: P,
+        sync_policy: SyncPolicy,
+    ) -> Result<RecordFile, SerialisationError> {
+        Self::open_with_index(path.as_ref(), sync_policy, false)
+    }
+
+    /// Like [`open()`](#method.open), but also keeps the offset of every record in memory, so
+    /// that [`get()`](#method.get) can read any record without scanning the file.
+    pub fn open_indexed<P: AsRef<Path>>(
+        path: P,
+        sync_policy: SyncPolicy,
+    ) -> Result<RecordFile, SerialisationError> {
+        Self::open_with_index(path.as_ref(), sync_policy, true)
+    }
+
+    fn open_with_index(
+        path: &Path,
+        sync_policy: SyncPolicy,
+        indexed: bool,
+    ) -> Result<RecordFile, SerialisationError> {
+        let file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)
+            .map_err(read_error)?;
+        let file_len = file.metadata().map_err(read_error)?.len();
+        let mut offsets = if indexed { Some(Vec::new()) } else { None };
+        let (end, len) = scan(&file, file_len, offsets.as_mut())?;
+        if end < file_len {
+            if !is_torn(&file, end, file_len)? {
+                return Err(SerialisationError::CorruptRecord(end));
+            }
+            file.set_len(end).map_err(write_error)?;
+            file.sync_data().map_err(write_error)?;
+        }
+        let reader = RecordReader {
+            reader: BufReader::new(File::open(path).map_err(read_error)?),
+            index: Some(0),
+            position: 0,
+        };
+        Ok(RecordFile {
+            file,
+            path: path.to_path_buf(),
+            sync_policy,
+            end,
+            len,
+            offsets,
+            reader: Mutex::new(reader),
+            unsynced: 0,
+            truncated: file_len - end,
+        })
+    }
+
+    /// Appends `data` as a new record, returning its index.
+    ///
+    /// If writing fails, any partially written record is truncated away.
+    pub fn append<T: Serialize>(&mut self, data: &T) -> Result<u64, SerialisationError> {
+        let mut record = vec![0; RECORD_HEADER_LEN];
+        let mut payload = Vec::new();
+        serialise_into_vec_with_limit(data, &mut payload, Bounded(u64::from(u32::MAX)))?;
+        let len_bytes = (payload.len() as u32).to_le_bytes();
+        let checksum = crc32c_append(crc32c(&len_bytes), &payload);
+        record[..4].copy_from_slice(&len_bytes);
+        record[4..].copy_from_slice(&checksum.to_le_bytes());
+        record.extend_from_slice(&payload);
+
+        if let Err(error) = self.file.write_all(&record) {
+            let _ = self.file.set_len(self.end);
+            // `get()` may have buffered some of the truncated bytes, so make it seek again.
+            unwrap!(self.reader.get_mut()).index = None;
+            return Err(write_error(error));
+        }
+        if let Some(ref mut offsets) = self.offsets {
+            offsets.push(self.end);
+        }
+        self.end += record.len() as u64;
+        self.len += 1;
+        self.unsynced += 1;
+
+        let sync = match self.sync_policy {
+            SyncPolicy::Never => false,
+            SyncPolicy::EveryRecord => true,
+            SyncPolicy::Every(records) => self.unsynced >= records,
+        };
+        if sync {
+            self.sync()?;
+        }
+        Ok(self.len - 1)
+    }
+
+    /// Flushes all appended records to disk.
+    pub fn sync(&mut self) -> Result<(), SerialisationError> {
+        self.file.sync_data().map_err(write_error)?;
+        self.unsynced = 0;
+        Ok(())
+    }
+
+    /// Reads the record with the given index, or returns `None` if there is no such record.
+    ///
+    /// Unless the file was opened with [`open_indexed()`](#method.open_indexed), this reads the
+    /// headers of the records between the last one read by `get()` and the requested one to find
+    /// it, or from the start of the file if the requested one comes earlier.  Reading records in
+    /// order is cheap, but reading them in any other order from a large file isn't; use
+    /// [`iter()`](#method.iter) or `open_indexed()` instead.  Only the requested record is decoded.
+    pub fn get<T: DeserializeOwned>(&self, index: u64) -> Result<Option<T>, SerialisationError> {
+        if index >= self.len {
+            return Ok(None);
+        }
+        let mut guard = unwrap!(self.reader.lock());
+        let state = &mut *guard;
+        let (mut current, offset) = match (self.offsets.as_ref(), state.index) {
+            (Some(offsets), _) => (index, offsets[index as usize]),
+            (None, Some(known)) if known <= index => (known, state.position),
+            (None, _) => (0, 0),
+        };
+        // The position is unknown until the record has been read, in case that fails part-way.
+        if state.index.take().is_none() || state.position != offset {
+            let _ = state
+                .reader
+                .seek(SeekFrom::Start(offset))
+                .map_err(read_error)?;
+            state.position = offset;
+        }
+        while current < index {
+            let (payload_len, _) = read_header(&mut state.reader)?;
+            state
+                .reader
+                .seek_relative(i64::from(payload_len))
+                .map_err(read_error)?;
+            state.position += RECORD_HEADER_LEN as u64 + u64::from(payload_len);
+            current += 1;
+        }
+        let payload = read_record(&mut state.reader)?;
+        state.position += (RECORD_HEADER_LEN + payload.len()) as u64;
+        state.index = Some(index + 1);
+        Bincode::deserialise(&payload, None).map(Some)
+    }
+
+    /// Returns an iterator over the records, in the order in which they were appended.
+    ///
+    /// Records appended after the iterator is created are not included.
+    pub fn iter<T: DeserializeOwned>(&self) -> RecordIter<T> {
+        RecordIter {
+            path: self.path.clone(),
+            reader: None,
+            position: 0,
+            end: self.end,
+            _item: PhantomData,
+        }
+    }
+
+    /// Returns the number of records in the file.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns `true` if the file contains no records.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of bytes of torn record which were truncated when the file was opened.
+    pub fn truncated_bytes(&self) -> u64 {
+        self.truncated
+    }
+}
+
+/// Iterator over the records of a [`RecordFile`](struct.RecordFile.html), returned by
+/// [`iter()`](struct.RecordFile.html#method.iter).
+///
+/// Each record is deserialised with the process-wide [default limits](fn.set_default_limits.html).
+/// After an error, the iterator is exhausted.
+pub struct RecordIter<T> {
+    path: PathBuf,
+    reader: Option<BufReader<File>>,
+    position: u64,
+    end: u64,
+    _item: PhantomData<fn() -> T>,
+}
+
+impl<T: DeserializeOwned> Iterator for RecordIter<T> {
+    type Item = Result<T, SerialisationError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.position >= self.end {
+            return None;
+        }
+        if self.reader.is_none() {
+            match File::open(&self.path) {
+                Ok(file) => self.reader = Some(BufReader::new(file)),
+                Err(error) => {
+                    self.position = self.end;
+                    return Some(Err(read_error(error)));
+                }
+            }
+        }
+        let reader = self.reader.as_mut()?;
+        let result = read_record(reader).and_then(|payload| {
+            self.position += (RECORD_HEADER_LEN + payload.len()) as u64;
+            Bincode::deserialise(&payload, None)
+        });
+        if result.is_err() {
+            self.position = self.end;
+        }
+        Some(result)
+    }
+}
+
+// Reads and checks the records of a newly opened file, returning the offset at which the valid
+// records end and the number of records.
+fn scan(
+    file: &File,
+    file_len: u64,
+    mut offsets: Option<&mut Vec<u64>>,
+) -> Result<(u64, u64), SerialisationError> {
+    let mut reader = BufReader::new(file);
+    let mut position = 0;
+    let mut len = 0;
+    while file_len - position >= RECORD_HEADER_LEN as u64 {
+        let (payload_len, checksum) = read_header(&mut reader)?;
+        let record_end = position + (RECORD_HEADER_LEN as u64) + u64::from(payload_len);
+        if record_end > file_len {
+            break;
+        }
+        if !payload_matches(&mut reader, payload_len, checksum)? {
+            break;
+        }
+        if let Some(ref mut offsets) = offsets {
+            offsets.push(position);
+        }
+        position = record_end;
+        len += 1;
+    }
+    Ok((position, len))
+}
+
+// Returns whether the bytes of `file` from `start` to `end`, which don't begin with a valid
+// record, could be what was left by an interrupted append: i.e. no longer than a record, and with
+// no valid record in them which ends at `end`, as the last of any records following a damaged one
+// would.  The bytes are read once, and only records whose length runs exactly to `end` are
+// checksummed.
+fn is_torn(file: &File, start: u64, end: u64) -> Result<bool, SerialisationError> {
+    let header_len = RECORD_HEADER_LEN as u64;
+    if end - start > MAX_RECORD_LEN {
+        return Ok(false);
+    }
+    if end - start <= header_len {
+        return Ok(true);
+    }
+
+    // Finds each offset after `start` whose length field gives a record ending at `end`.
+    let mut reader = BufReader::new(file);
+    let _ = reader
+        .seek(SeekFrom::Start(start + 1))
+        .map_err(read_error)?;
+    let mut candidates = Vec::new();
+    let mut window = 0u32;
+    let length_fields = (&mut reader).take(end - header_len + 4 - (start + 1));
+    for (index, byte) in length_fields.bytes().enumerate() {
+        window = (window >> 8) | (u32::from(byte.map_err(read_error)?) << 24);
+        if index < 3 {
+            continue;
+        }
+        let offset = start + 1 + index as u64 - 3;
+        if u64::from(window) == end - offset - header_len {
+            if candidates.len() == MAX_TAIL_CANDIDATES {
+                return Ok(false);
+            }
+            candidates.push(offset);
+        }
+    }
+
+    for offset in candidates {
+        let _ = reader.seek(SeekFrom::Start(offset)).map_err(read_error)?;
+        let (payload_len, checksum) = read_header(&mut reader)?;
+        if payload_matches(&mut reader, payload_len, checksum)? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+// Reads a payload of `len` bytes from `reader` in chunks, returning whether it matches `checksum`.
+fn payload_matches<R: Read>(
+    reader: &mut R,
+    len: u32,
+    checksum: u32,
+) -> Result<bool, SerialisationError> {
+    let mut crc = crc32c(&len.to_le_bytes());
+    let mut payload = reader.take(u64::from(len));
+    let mut chunk = [0; 4096];
+    loop {
+        let read = payload.read(&mut chunk).map_err(read_error)?;
+        if read == 0 {
+            break;
+        }
+        crc = crc32c_append(crc, &chunk[..read]);
+    }
+    if payload.limit() > 0 {
+        return Err(read_error(io::ErrorKind::UnexpectedEof.into()));
+    }
+    Ok(crc == checksum)
+}
+
+fn read_header<R: Read>(reader: &mut R) -> Result<(u32, u32), SerialisationError> {
+    let mut header = [0; RECORD_HEADER_LEN];
+    reader.read_exact(&mut header).map_err(read_error)?;
+    let mut len_bytes = [0; 4];
+    let mut checksum_bytes = [0; 4];
+    len_bytes.copy_from_slice(&header[..4]);
+    checksum_bytes.copy_from_slice(&header[4..]);
+    Ok((
+        u32::from_le_bytes(len_bytes),
+        u32::from_le_bytes(checksum_bytes),
+    ))
+}
+
+fn read_record<R: Read>(reader: &mut R) -> Result<Vec<u8>, SerialisationError> {
+    let (len, checksum) = read_header(reader)?;
+    // The payload grows as it is read, rather than being allocated up front from the unverified
+    // header.
+    let mut payload = Vec::new();
+    let _ = reader
+        .take(u64::from(len))
+        .read_to_end(&mut payload)
+        .map_err(read_error)?;
+    if payload.len() as u64 != u64::from(len) {
+        return Err(read_error(io::ErrorKind::UnexpectedEof.into()));
+    }
+    if crc32c_append(crc32c(&len.to_le_bytes()), &payload) != checksum {
+        return Err(SerialisationError::ChecksumMismatch);
+    }
+    Ok(payload)
+}
+
+fn read_error(error: io::Error) -> SerialisationError {
+    SerialisationError::Deserialise(ErrorKind::IoError(error), None)
+}
+
+fn write_error(error: io::Error) -> SerialisationError {
+    SerialisationError::Serialise(ErrorKind::IoError(error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs, process};
+
+    fn temp_path(name: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!(
+            "maidsafe_utilities_record_file_{}_{}",
+            name,
+            process::id()
+        ));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn records(file: &RecordFile) -> Vec<(u32, String)> {
+        unwrap!(file.iter().collect::<Result<Vec<_>, _>>())
+    }
+
+    #[test]
+    fn append_and_read() {
+        let path = temp_path("append");
+        let expected = (0..10u32)
+            .map(|index| (index, "record".repeat(index as usize)))
+            .collect::<Vec<_>>();
+        {
+            let mut file = unwrap!(RecordFile::open(&path, SyncPolicy::Every(4)));
+            assert!(file.is_empty());
+            for (index, record) in expected.iter().enumerate() {
+                assert_eq!(unwrap!(file.append(record)), index as u64);
+            }
+            assert_eq!(records(&file), expected);
+        }
+
+        // Reopening keeps the existing records and appends after them.
+        let mut file = unwrap!(RecordFile::open(&path, SyncPolicy::Never));
+        assert_eq!(file.len(), 10);
+        assert_eq!(file.truncated_bytes(), 0);
+        assert_eq!(
+            unwrap!(file.get::<(u32, String)>(3)),
+            Some(expected[3].clone())
+        );
+        assert_eq!(unwrap!(file.get::<(u32, String)>(10)), None);
+        for index in (0..10).chain((0..10).rev()) {
+            assert_eq!(
+                unwrap!(file.get::<(u32, String)>(index)),
+                Some(expected[index as usize].clone())
+            );
+        }
+        assert_eq!(unwrap!(file.append(&(10u32, String::new()))), 10);
+        assert_eq!(
+            unwrap!(file.get::<(u32, String)>(10)),
+            Some((10, String::new()))
+        );
+
+        let file = unwrap!(RecordFile::open_indexed(&path, SyncPolicy::EveryRecord));
+        assert_eq!(file.len(), 11);
+        assert_eq!(
+            unwrap!(file.get::<(u32, String)>(7)),
+            Some(expected[7].clone())
+        );
+        assert_eq!(
+            unwrap!(file.get::<(u32, String)>(10)),
+            Some((10, String::new()))
+        );
+        unwrap!(fs::remove_file(&path));
+    }
+
+    #[test]
+    fn torn_record_recovery() {
+        let path = temp_path("torn");
+        {
+            let mut file = unwrap!(RecordFile::open(&path, SyncPolicy::Never));
+            for index in 0..3u32 {
+                let _ = unwrap!(file.append(&(index, index.to_string())));
+            }
+        }
+        let full_len = unwrap!(fs::metadata(&path)).len();
+        let record_len = full_len / 3;
+
+        // A crash part-way through appending a fourth record, and then one which corrupted the
+        // tail of the third.
+        let mut torn = unwrap!(OpenOptions::new().append(true).open(&path));
+        unwrap!(torn.write_all(&[9, 0, 0, 0, 1, 2]));
+        drop(torn);
+        let file = unwrap!(RecordFile::open_indexed(&path, SyncPolicy::EveryRecord));
+        assert_eq!(file.truncated_bytes(), 6);
+        assert_eq!(file.len(), 3);
+        assert_eq!(unwrap!(fs::metadata(&path)).len(), full_len);
+        drop(file);
+
+        // A torn record whose payload happens to contain a whole record is still torn.
+        let first_record = unwrap!(fs::read(&path))[..record_len as usize].to_vec();
+        let mut torn = unwrap!(OpenOptions::new().append(true).open(&path));
+        unwrap!(torn.write_all(&[200, 0, 0, 0, 1, 2, 3, 4]));
+        unwrap!(torn.write_all(&first_record));
+        unwrap!(torn.write_all(&[5, 6]));
+        drop(torn);
+        let file = unwrap!(RecordFile::open(&path, SyncPolicy::Never));
+        assert_eq!(file.truncated_bytes(), record_len + 10);
+        assert_eq!(file.len(), 3);
+        drop(file);
+
+        let mut contents = unwrap!(fs::read(&path));
+        let last = contents.len() - 1;
+        contents[last] ^= 0xff;
+        unwrap!(fs::write(&path, &contents));
+        let mut file = unwrap!(RecordFile::open(&path, SyncPolicy::Never));
+        assert_eq!(file.truncated_bytes(), record_len);
+        assert_eq!(
+            records(&file),
+            vec![(0, "0".to_owned()), (1, "1".to_owned())]
+        );
+        assert_eq!(unwrap!(file.append(&(2u32, "2".to_owned()))), 2);
+        drop(file);
+
+        // Damage to any record but the last isn't mistaken for a torn append.
+        let mut contents = unwrap!(fs::read(&path));
+        contents[RECORD_HEADER_LEN] ^= 0xff;
+        unwrap!(fs::write(&path, &contents));
+        match RecordFile::open(&path, SyncPolicy::Never) {
+            Err(SerialisationError::CorruptRecord(0)) => (),
+            result => panic!("{:?}", result.map(|file| file.len())),
+        }
+
+        // Nor is a length which has been corrupted to run past the end of the file.
+        contents[RECORD_HEADER_LEN] ^= 0xff;
+        contents[record_len as usize + 2] = 0xff;
+        unwrap!(fs::write(&path, &contents));
+        match RecordFile::open(&path, SyncPolicy::Never) {
+            Err(SerialisationError::CorruptRecord(offset)) => assert_eq!(offset, record_len),
+            result => panic!("{:?}", result.map(|file| file.len())),
+        }
+        assert_eq!(unwrap!(fs::read(&path)), contents);
+        unwrap!(fs::remove_file(&path));
+    }
+
+    #[test]
+    fn get_decodes_only_requested_record() {
+        let path = temp_path("get");
+        let mut file = unwrap!(RecordFile::open(&path, SyncPolicy::Never));
+        // The first record is too short to decode as a `u64`.
+        let _ = unwrap!(file.append(&1u8));
+        let _ = unwrap!(file.append(&7u64));
+        assert_eq!(unwrap!(file.get::<u64>(1)), Some(7));
+        let _ = unwrap_err!(unwrap!(file.iter::<u64>().next()));
+
+        // A length corrupted after the file was opened is reported as running past the end of the
+        // file, without a buffer of that length being allocated.
+        let mut contents = unwrap!(fs::read(&path));
+        contents[RECORD_HEADER_LEN + 1..RECORD_HEADER_LEN + 5].copy_from_slice(&[0xff; 4]);
+        unwrap!(fs::write(&path, &contents));
+        match unwrap_err!(file.get::<u64>(1)) {
+            SerialisationError::Deserialise(ErrorKind::IoError(ref error), _)
+                if error.kind() == io::ErrorKind::UnexpectedEof => {}
+            err => panic!("{:?}", err),
+        }
+        unwrap!(fs::remove_file(&path));
+    }
+}
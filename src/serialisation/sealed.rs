@@ -0,0 +1,168 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// https://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+use super::{Bincode, Format, SerialisationError};
+use bincode::ErrorKind;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use serde::de::DeserializeOwned;
+use serde::ser::Serialize;
+
+/// Length of the symmetric key used by [`serialise_sealed()`](fn.serialise_sealed.html).
+pub const SEALING_KEY_LEN: usize = 32;
+
+/// Length of the random nonce prefixed to every sealed payload.
+pub const SEALING_NONCE_LEN: usize = 24;
+
+/// Serialise an `Serialize` type and encrypt it with XChaCha20-Poly1305 under `key`.
+///
+/// The output is a random nonce followed by the ciphertext and authentication tag.
+pub fn serialise_sealed<T: Serialize>(
+    data: &T,
+    key: &[u8; SEALING_KEY_LEN],
+) -> Result<Vec<u8>, SerialisationError> {
+    serialise_sealed_with_aad(data, key, &[])
+}
+
+/// Serialise and encrypt an `Serialize` type as per
+/// [`serialise_sealed()`](fn.serialise_sealed.html), binding the result to `associated_data`.
+///
+/// The associated data is authenticated but not included in the output; the same data must be
+/// passed to [`deserialise_sealed_with_aad()`](fn.deserialise_sealed_with_aad.html).
+pub fn serialise_sealed_with_aad<T: Serialize>(
+    data: &T,
+    key: &[u8; SEALING_KEY_LEN],
+    associated_data: &[u8],
+) -> Result<Vec<u8>, SerialisationError> {
+    let plaintext = Bincode::serialise(data, None)?;
+    let cipher = XChaCha20Poly1305::new(key.into());
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: &plaintext,
+                aad: associated_data,
+            },
+        )
+        .map_err(|_| {
+            SerialisationError::Serialise(ErrorKind::Custom("encryption failed".to_owned()))
+        })?;
+
+    let mut sealed = Vec::with_capacity(SEALING_NONCE_LEN + ciphertext.len());
+    sealed.extend_from_slice(&nonce);
+    sealed.extend_from_slice(&ciphertext);
+    Ok(sealed)
+}
+
+/// Decrypt and deserialise a `Deserialize` type written by
+/// [`serialise_sealed()`](fn.serialise_sealed.html).
+///
+/// Fails with `SerialisationError::Decryption` if the key is wrong or the data has been tampered
+/// with.
+pub fn deserialise_sealed<T: DeserializeOwned>(
+    data: &[u8],
+    key: &[u8; SEALING_KEY_LEN],
+) -> Result<T, SerialisationError> {
+    deserialise_sealed_with_aad(data, key, &[])
+}
+
+/// Decrypt and deserialise a `Deserialize` type written by
+/// [`serialise_sealed_with_aad()`](fn.serialise_sealed_with_aad.html).
+///
+/// Fails with `SerialisationError::Decryption` if the key or associated data is wrong, or the data
+/// has been tampered with.
+pub fn deserialise_sealed_with_aad<T: DeserializeOwned>(
+    data: &[u8],
+    key: &[u8; SEALING_KEY_LEN],
+    associated_data: &[u8],
+) -> Result<T, SerialisationError> {
+    if data.len() < SEALING_NONCE_LEN {
+        return Err(SerialisationError::Decryption);
+    }
+    let (nonce, ciphertext) = data.split_at(SEALING_NONCE_LEN);
+    let cipher = XChaCha20Poly1305::new(key.into());
+    let plaintext = cipher
+        .decrypt(
+            XNonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad: associated_data,
+            },
+        )
+        .map_err(|_| SerialisationError::Decryption)?;
+    Bincode::deserialise(&plaintext, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serialisation::serialise;
+
+    const KEY: [u8; SEALING_KEY_LEN] = [42; SEALING_KEY_LEN];
+
+    #[test]
+    fn sealed_round_trip() {
+        let original_data = (vec![0u8, 1, 3, 9], "SomeString".to_string());
+        let sealed = unwrap!(serialise_sealed(&original_data, &KEY));
+        let plaintext = unwrap!(serialise(&original_data));
+        assert_eq!(sealed.len(), SEALING_NONCE_LEN + plaintext.len() + 16);
+        assert!(!sealed.windows(10).any(|window| window == b"SomeString"));
+
+        // Nonces are random, so sealing twice gives different output.
+        assert_ne!(sealed, unwrap!(serialise_sealed(&original_data, &KEY)));
+
+        let opened: (Vec<u8>, String) = unwrap!(deserialise_sealed(&sealed, &KEY));
+        assert_eq!(original_data, opened);
+
+        let mut wrong_key = KEY;
+        wrong_key[0] ^= 1;
+        match unwrap_err!(deserialise_sealed::<(Vec<u8>, String)>(&sealed, &wrong_key)) {
+            SerialisationError::Decryption => (),
+            err => panic!("{:?}", err),
+        }
+
+        let mut tampered = sealed.clone();
+        tampered[SEALING_NONCE_LEN] ^= 1;
+        match unwrap_err!(deserialise_sealed::<(Vec<u8>, String)>(&tampered, &KEY)) {
+            SerialisationError::Decryption => (),
+            err => panic!("{:?}", err),
+        }
+
+        match unwrap_err!(deserialise_sealed::<(Vec<u8>, String)>(&sealed[..8], &KEY)) {
+            SerialisationError::Decryption => (),
+            err => panic!("{:?}", err),
+        }
+    }
+
+    #[test]
+    fn sealed_associated_data() {
+        let sealed = unwrap!(serialise_sealed_with_aad(&7u64, &KEY, b"chunk-store"));
+        assert_eq!(
+            7,
+            unwrap!(deserialise_sealed_with_aad::<u64>(
+                &sealed,
+                &KEY,
+                b"chunk-store"
+            ))
+        );
+        match unwrap_err!(deserialise_sealed_with_aad::<u64>(
+            &sealed,
+            &KEY,
+            b"routing-table"
+        )) {
+            SerialisationError::Decryption => (),
+            err => panic!("{:?}", err),
+        }
+        match unwrap_err!(deserialise_sealed::<u64>(&sealed, &KEY)) {
+            SerialisationError::Decryption => (),
+            err => panic!("{:?}", err),
+        }
+    }
+}
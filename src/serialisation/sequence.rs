@@ -0,0 +1,238 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// https://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+use super::limits::default_limits;
+use super::{FrameReader, FrameWriter, SerialisationError};
+use bincode::{Bounded, ErrorKind};
+use serde::de::DeserializeOwned;
+use serde::ser::Serialize;
+use std::io::{self, Read, Write};
+use std::marker::PhantomData;
+
+/// Serialise every item of an iterator directly into a `Write`, one after another, with no limit
+/// on the size of each serialised item other than that of the frame header.
+///
+/// Each item is written as it is produced, as a frame prefixed with its length in the same format
+/// as a [`FrameWriter`](struct.FrameWriter.html), so that every item (even one which serialises
+/// to no bytes at all) can be read back with [`deserialise_iter()`](fn.deserialise_iter.html).
+/// Returns the number of items written.
+pub fn serialise_iter<I, W>(items: I, write: &mut W) -> Result<u64, SerialisationError>
+where
+    I: IntoIterator,
+    I::Item: Serialize,
+    W: Write,
+{
+    serialise_iter_with_limit(items, write, Bounded(u64::MAX))
+}
+
+/// Serialise every item of an iterator directly into a `Write`, with max size limit specified
+/// for each serialised item.
+///
+/// Items preceding one which exceeds the limit will already have been written, and the one
+/// exceeding it yields `SerialisationError::FrameTooLarge`.
+pub fn serialise_iter_with_limit<I, W>(
+    items: I,
+    write: &mut W,
+    size_limit: Bounded,
+) -> Result<u64, SerialisationError>
+where
+    I: IntoIterator,
+    I::Item: Serialize,
+    W: Write,
+{
+    let mut frames = FrameWriter::new(write, size_limit);
+    let mut count = 0;
+    for item in items {
+        frames.write_frame_unflushed(&item)?;
+        count += 1;
+    }
+    if !frames.flush()? {
+        return Err(SerialisationError::Serialise(ErrorKind::IoError(
+            io::ErrorKind::WouldBlock.into(),
+        )));
+    }
+    Ok(count)
+}
+
+/// Returns an iterator which lazily deserialises items written by
+/// [`serialise_iter()`](fn.serialise_iter.html) from a blocking `Read`, with the process-wide
+/// [default limits](fn.set_default_limits.html) applied to each item.
+pub fn deserialise_iter<T: DeserializeOwned, R: Read>(read: R) -> DeserialiseIter<T, R> {
+    DeserialiseIter::new(read, Bounded(default_limits().max_bytes))
+}
+
+/// Returns an iterator which lazily deserialises items written by
+/// [`serialise_iter()`](fn.serialise_iter.html) from a blocking `Read`, with max size limit
+/// specified for each item.
+pub fn deserialise_iter_with_limit<T: DeserializeOwned, R: Read>(
+    read: R,
+    size_limit: Bounded,
+) -> DeserialiseIter<T, R> {
+    DeserialiseIter::new(read, size_limit)
+}
+
+/// Iterator returned by [`deserialise_iter()`](fn.deserialise_iter.html).
+///
+/// Yields `Ok` for each item until the reader reaches end of file between two items.  Running out
+/// of input part way through an item (`SerialisationError::TruncatedFrame`), an item exceeding the
+/// size limit (`SerialisationError::FrameTooLarge`), or any other failure, yields a single `Err`,
+/// after which the iterator is exhausted.
+pub struct DeserialiseIter<T, R> {
+    frames: FrameReader<R>,
+    finished: bool,
+    _item: PhantomData<fn() -> T>,
+}
+
+impl<T: DeserializeOwned, R: Read> DeserialiseIter<T, R> {
+    fn new(read: R, size_limit: Bounded) -> Self {
+        DeserialiseIter {
+            frames: FrameReader::new(read, size_limit),
+            finished: false,
+            _item: PhantomData,
+        }
+    }
+
+    /// Unwraps this `DeserialiseIter`, returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.frames.into_inner()
+    }
+}
+
+impl<T: DeserializeOwned, R: Read> Iterator for DeserialiseIter<T, R> {
+    type Item = Result<T, SerialisationError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        let result = match self.frames.read_frame() {
+            Ok(Some(item)) => Ok(item),
+            Ok(None) if self.frames.is_closed() => {
+                self.finished = true;
+                return None;
+            }
+            // The reader isn't blocking.
+            Ok(None) => Err(SerialisationError::Deserialise(
+                ErrorKind::IoError(io::ErrorKind::WouldBlock.into()),
+                None,
+            )),
+            Err(error) => Err(error),
+        };
+        self.finished = result.is_err();
+        Some(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serialisation::{serialise, FRAME_HEADER_LEN};
+    use std::io::Cursor;
+
+    #[test]
+    fn round_trip() {
+        let items = [
+            (0u8, "zero".to_owned()),
+            (1, String::new()),
+            (2, "two".to_owned()),
+        ];
+        let mut serialised = Vec::new();
+        // Items are taken by reference, straight from an iterator adaptor.
+        let written = unwrap!(serialise_iter(
+            items.iter().filter(|item| item.0 != 1),
+            &mut serialised
+        ));
+        assert_eq!(written, 2);
+        let first = unwrap!(serialise(&items[0]));
+        assert_eq!(
+            serialised[..FRAME_HEADER_LEN],
+            (first.len() as u32).to_le_bytes()
+        );
+        assert_eq!(serialised[FRAME_HEADER_LEN..][..first.len()], first[..]);
+
+        let deserialised = unwrap!(deserialise_iter::<(u8, String), _>(Cursor::new(
+            &serialised[..]
+        ))
+        .collect::<Result<Vec<_>, _>>());
+        assert_eq!(deserialised, vec![items[0].clone(), items[2].clone()]);
+
+        // An empty input is an empty sequence.
+        assert!(deserialise_iter::<u64, _>(&[][..]).next().is_none());
+
+        // Items which serialise to no bytes are still counted.
+        let mut serialised = Vec::new();
+        assert_eq!(unwrap!(serialise_iter(vec![(); 3], &mut serialised)), 3);
+        assert_eq!(deserialise_iter::<(), _>(&serialised[..]).count(), 3);
+    }
+
+    #[test]
+    fn truncated_and_oversized_items() {
+        let mut serialised = Vec::new();
+        let _ = unwrap!(serialise_iter([1u64, 2, 3], &mut serialised));
+        let _ = serialised.pop();
+
+        let mut iter = deserialise_iter::<u64, _>(&serialised[..]);
+        assert_eq!(unwrap!(unwrap!(iter.next())), 1);
+        assert_eq!(unwrap!(unwrap!(iter.next())), 2);
+        match unwrap!(iter.next()) {
+            Err(SerialisationError::TruncatedFrame(11, 12)) => (),
+            result => panic!("{:?}", result),
+        }
+        assert!(iter.next().is_none());
+
+        let items = vec![vec![0u8; 4], vec![0u8; 40]];
+        let mut serialised = Vec::new();
+        match unwrap_err!(serialise_iter_with_limit(
+            &items,
+            &mut serialised,
+            Bounded(20)
+        )) {
+            SerialisationError::FrameTooLarge(48, 20) => (),
+            err => panic!("{:?}", err),
+        }
+        assert_eq!(serialised.len(), FRAME_HEADER_LEN + 12);
+
+        let _ = unwrap!(serialise_iter(&items, &mut serialised));
+        let mut iter = deserialise_iter_with_limit::<Vec<u8>, _>(&serialised[..], Bounded(20));
+        assert_eq!(unwrap!(unwrap!(iter.next())), items[0]);
+        assert_eq!(unwrap!(unwrap!(iter.next())), items[0]);
+        match unwrap!(iter.next()) {
+            Err(SerialisationError::FrameTooLarge(48, 20)) => (),
+            result => panic!("{:?}", result),
+        }
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    fn single_flush() {
+        struct CountFlushes {
+            written: Vec<u8>,
+            flushes: usize,
+        }
+
+        impl Write for CountFlushes {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.written.write(buf)
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                self.flushes += 1;
+                Ok(())
+            }
+        }
+
+        let mut write = CountFlushes {
+            written: Vec::new(),
+            flushes: 0,
+        };
+        assert_eq!(unwrap!(serialise_iter(0u64..10, &mut write)), 10);
+        assert_eq!(write.flushes, 1);
+        assert_eq!(deserialise_iter::<u64, _>(&write.written[..]).count(), 10);
+    }
+}
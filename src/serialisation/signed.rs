@@ -0,0 +1,157 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// https://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+use super::{Bincode, Format, SerialisationError};
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use serde::de::DeserializeOwned;
+use serde::ser::Serialize;
+
+/// Length of an Ed25519 secret key.
+pub const SECRET_KEY_LEN: usize = 32;
+
+/// Length of an Ed25519 public key.
+pub const PUBLIC_KEY_LEN: usize = 32;
+
+/// Length of an Ed25519 signature.
+pub const SIGNATURE_LEN: usize = 64;
+
+/// Returns the public key which verifies signatures made with `secret_key`.
+pub fn derive_public_key(secret_key: &[u8; SECRET_KEY_LEN]) -> [u8; PUBLIC_KEY_LEN] {
+    SigningKey::from_bytes(secret_key)
+        .verifying_key()
+        .to_bytes()
+}
+
+/// Serialise an `Serialize` type and sign the serialised bytes with `secret_key`.
+///
+/// The output is the Ed25519 signature followed by the signed payload.
+pub fn serialise_signed<T: Serialize>(
+    data: &T,
+    secret_key: &[u8; SECRET_KEY_LEN],
+) -> Result<Vec<u8>, SerialisationError> {
+    let (payload, signature) = serialise_signed_detached(data, secret_key)?;
+    let mut signed = Vec::with_capacity(SIGNATURE_LEN + payload.len());
+    signed.extend_from_slice(&signature);
+    signed.extend_from_slice(&payload);
+    Ok(signed)
+}
+
+/// Serialise an `Serialize` type and sign the serialised bytes with `secret_key`, returning the
+/// payload and its signature separately.
+pub fn serialise_signed_detached<T: Serialize>(
+    data: &T,
+    secret_key: &[u8; SECRET_KEY_LEN],
+) -> Result<(Vec<u8>, [u8; SIGNATURE_LEN]), SerialisationError> {
+    let payload = Bincode::serialise(data, None)?;
+    let signature = SigningKey::from_bytes(secret_key).sign(&payload);
+    Ok((payload, signature.to_bytes()))
+}
+
+/// Verify and deserialise a `Deserialize` type written by
+/// [`serialise_signed()`](fn.serialise_signed.html).
+///
+/// The signature is checked against `public_key` before any decoding is attempted, failing with
+/// `SerialisationError::InvalidSignature`.
+pub fn deserialise_verified<T: DeserializeOwned>(
+    data: &[u8],
+    public_key: &[u8; PUBLIC_KEY_LEN],
+) -> Result<T, SerialisationError> {
+    if data.len() < SIGNATURE_LEN {
+        return Err(SerialisationError::InvalidSignature);
+    }
+    let (signature_bytes, payload) = data.split_at(SIGNATURE_LEN);
+    let mut signature = [0; SIGNATURE_LEN];
+    signature.copy_from_slice(signature_bytes);
+    deserialise_verified_detached(payload, &signature, public_key)
+}
+
+/// Verify and deserialise a `Deserialize` type written by
+/// [`serialise_signed_detached()`](fn.serialise_signed_detached.html).
+///
+/// The signature is checked against `public_key` before any decoding is attempted, failing with
+/// `SerialisationError::InvalidSignature`.  A malformed public key fails in the same way.
+pub fn deserialise_verified_detached<T: DeserializeOwned>(
+    data: &[u8],
+    signature: &[u8; SIGNATURE_LEN],
+    public_key: &[u8; PUBLIC_KEY_LEN],
+) -> Result<T, SerialisationError> {
+    let public_key =
+        VerifyingKey::from_bytes(public_key).map_err(|_| SerialisationError::InvalidSignature)?;
+    public_key
+        .verify_strict(data, &Signature::from_bytes(signature))
+        .map_err(|_| SerialisationError::InvalidSignature)?;
+    Bincode::deserialise(data, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serialisation::serialise;
+
+    #[test]
+    fn attached_signature() {
+        let secret_key = [1; SECRET_KEY_LEN];
+        let public_key = derive_public_key(&secret_key);
+        let original_data = (vec![0u8, 1, 3, 9], "SomeString".to_string());
+
+        let signed = unwrap!(serialise_signed(&original_data, &secret_key));
+        assert_eq!(
+            signed[SIGNATURE_LEN..],
+            unwrap!(serialise(&original_data))[..]
+        );
+        let verified: (Vec<u8>, String) = unwrap!(deserialise_verified(&signed, &public_key));
+        assert_eq!(original_data, verified);
+
+        let other_key = derive_public_key(&[2; SECRET_KEY_LEN]);
+        match unwrap_err!(deserialise_verified::<(Vec<u8>, String)>(
+            &signed, &other_key
+        )) {
+            SerialisationError::InvalidSignature => (),
+            err => panic!("{:?}", err),
+        }
+
+        let mut tampered = signed.clone();
+        let last = tampered.len() - 1;
+        tampered[last] ^= 1;
+        match unwrap_err!(deserialise_verified::<(Vec<u8>, String)>(
+            &tampered,
+            &public_key
+        )) {
+            SerialisationError::InvalidSignature => (),
+            err => panic!("{:?}", err),
+        }
+    }
+
+    #[test]
+    fn detached_signature() {
+        let secret_key = [1; SECRET_KEY_LEN];
+        let public_key = derive_public_key(&secret_key);
+
+        let (payload, signature) = unwrap!(serialise_signed_detached(&7u64, &secret_key));
+        assert_eq!(payload, unwrap!(serialise(&7u64)));
+        assert_eq!(
+            7,
+            unwrap!(deserialise_verified_detached::<u64>(
+                &payload,
+                &signature,
+                &public_key
+            ))
+        );
+
+        let other_payload = unwrap!(serialise(&8u64));
+        match unwrap_err!(deserialise_verified_detached::<u64>(
+            &other_payload,
+            &signature,
+            &public_key
+        )) {
+            SerialisationError::InvalidSignature => (),
+            err => panic!("{:?}", err),
+        }
+    }
+}
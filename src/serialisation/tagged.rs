@@ -0,0 +1,730 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// https://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+use super::{Bincode, Format, SerialisationError};
+use bincode::{Bounded, ErrorKind};
+use serde::de::{
+    self, DeserializeOwned, DeserializeSeed, Deserializer, EnumAccess, IntoDeserializer, MapAccess,
+    SeqAccess, VariantAccess, Visitor,
+};
+use serde::ser::Serialize;
+use std::any::TypeId;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::io;
+use std::mem;
+use std::sync::Mutex;
+
+/// Length of the type tag written before every tagged payload: a little-endian `u64`.
+pub const TYPE_TAG_LEN: usize = 8;
+
+// Sequences, maps and options nested deeper than this are traced as empty, so that recursive types
+// have a finite shape.
+const MAX_UNROLLED_DEPTH: usize = 16;
+// Tracing fails for types nested deeper than this.
+const MAX_TRACE_DEPTH: usize = 64;
+
+lazy_static! {
+    static ref FINGERPRINTS: Mutex<HashMap<TypeId, u64>> = Mutex::new(HashMap::new());
+}
+
+/// A type which can be written with a type tag via
+/// [`serialise_tagged()`](fn.serialise_tagged.html), so that deserialising the payload as a
+/// different type fails with `SerialisationError::TypeMismatch`.
+pub trait Tagged: Serialize + DeserializeOwned + 'static {
+    /// An explicit type ID to use as the tag.  By default, the tag is the type's
+    /// [fingerprint](fn.type_fingerprint.html).
+    const TYPE_ID: Option<u64> = None;
+}
+
+/// Returns a stable fingerprint of the shape of `T` as seen by serde: the names of its structs,
+/// fields, enums and variants, and the types of its primitive values.
+///
+/// The fingerprint is derived from `T`'s `Deserialize` implementation by decoding placeholder
+/// values, once for each variant of every enum it contains.  Recursive enums are supported as long
+/// as some variant of each can be decoded without recursing.  Placeholders are zero or empty
+/// values, falling back to non-zero, non-empty ones for types which reject those.
+///
+/// Tracing fails for types which reject both sets of placeholders, or which rely on
+/// `deserialize_any()`.  Such types should provide a [`Tagged::TYPE_ID`](trait.Tagged.html).
+pub fn type_fingerprint<T: DeserializeOwned + 'static>() -> Result<u64, SerialisationError> {
+    if let Some(fingerprint) = unwrap!(FINGERPRINTS.lock()).get(&TypeId::of::<T>()) {
+        return Ok(*fingerprint);
+    }
+    let shape = trace::<T>(false)
+        .or_else(|error| trace::<T>(true).map_err(|_| error))
+        .map_err(|error| SerialisationError::TypeFingerprint(error.0))?;
+    let fingerprint = fnv1a(shape.as_bytes());
+    let _ = unwrap!(FINGERPRINTS.lock()).insert(TypeId::of::<T>(), fingerprint);
+    Ok(fingerprint)
+}
+
+/// Serialise a `Tagged` type prefixed with its type tag, with no limit on the size of the
+/// serialised data.
+pub fn serialise_tagged<T: Tagged>(data: &T) -> Result<Vec<u8>, SerialisationError> {
+    Ok(with_tag(type_tag::<T>()?, &Bincode::serialise(data, None)?))
+}
+
+/// Serialise a `Tagged` type prefixed with its type tag, with max limit specified.
+///
+/// The limit applies to the payload and excludes the tag.
+pub fn serialise_tagged_with_limit<T: Tagged>(
+    data: &T,
+    size_limit: Bounded,
+) -> Result<Vec<u8>, SerialisationError> {
+    Ok(with_tag(
+        type_tag::<T>()?,
+        &Bincode::serialise(data, Some(size_limit))?,
+    ))
+}
+
+/// Deserialise a `Tagged` type written by [`serialise_tagged()`](fn.serialise_tagged.html) with
+/// the process-wide [default limits](fn.set_default_limits.html).
+///
+/// The type tag is checked before any decoding is attempted.
+pub fn deserialise_tagged<T: Tagged>(data: &[u8]) -> Result<T, SerialisationError> {
+    Bincode::deserialise(check_tag::<T>(data)?, None)
+}
+
+/// Deserialise a `Tagged` type written by [`serialise_tagged()`](fn.serialise_tagged.html) with
+/// max size limit specified.
+///
+/// The limit applies to the payload and excludes the tag.
+pub fn deserialise_tagged_with_limit<T: Tagged>(
+    data: &[u8],
+    size_limit: Bounded,
+) -> Result<T, SerialisationError> {
+    Bincode::deserialise(check_tag::<T>(data)?, Some(size_limit))
+}
+
+/// Returns the type tag recorded at the start of a tagged payload.
+pub fn tagged_type(data: &[u8]) -> Result<u64, SerialisationError> {
+    split_tag(data).map(|(tag, _)| tag)
+}
+
+fn type_tag<T: Tagged>() -> Result<u64, SerialisationError> {
+    match T::TYPE_ID {
+        Some(id) => Ok(id),
+        None => type_fingerprint::<T>(),
+    }
+}
+
+fn with_tag(tag: u64, payload: &[u8]) -> Vec<u8> {
+    let mut tagged = Vec::with_capacity(TYPE_TAG_LEN + payload.len());
+    tagged.extend_from_slice(&tag.to_le_bytes());
+    tagged.extend_from_slice(payload);
+    tagged
+}
+
+fn split_tag(data: &[u8]) -> Result<(u64, &[u8]), SerialisationError> {
+    if data.len() < TYPE_TAG_LEN {
+        return Err(SerialisationError::Deserialise(
+            ErrorKind::IoError(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "missing type tag",
+            )),
+            None,
+        ));
+    }
+    let mut tag = [0; TYPE_TAG_LEN];
+    tag.copy_from_slice(&data[..TYPE_TAG_LEN]);
+    Ok((u64::from_le_bytes(tag), &data[TYPE_TAG_LEN..]))
+}
+
+fn check_tag<T: Tagged>(data: &[u8]) -> Result<&[u8], SerialisationError> {
+    let (found, payload) = split_tag(data)?;
+    let expected = type_tag::<T>()?;
+    if found != expected {
+        return Err(SerialisationError::TypeMismatch(expected, found));
+    }
+    Ok(payload)
+}
+
+// 64-bit FNV-1a, which unlike `std`'s hashers is guaranteed stable across releases.
+fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[derive(Debug)]
+struct TraceError(String);
+
+impl Display for TraceError {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        formatter.write_str(&self.0)
+    }
+}
+
+impl Error for TraceError {}
+
+impl de::Error for TraceError {
+    fn custom<T: Display>(msg: T) -> Self {
+        TraceError(msg.to_string())
+    }
+}
+
+// Decodes `T` from a `Tracer` repeatedly until every variant of every enum it contains has been
+// traced, returning the shape of `T` followed by that of each enum.
+fn trace<T: DeserializeOwned>(nonzero: bool) -> Result<String, TraceError> {
+    let mut tracer = Tracer {
+        shape: String::new(),
+        depth: 0,
+        nonzero,
+        enums: BTreeMap::new(),
+        tracing: Vec::new(),
+        failed: HashSet::new(),
+        changes: 0,
+    };
+    loop {
+        let changes = tracer.changes;
+        tracer.shape.clear();
+        let result = T::deserialize(&mut tracer);
+        let complete = tracer
+            .enums
+            .values()
+            .all(|variants| variants.iter().all(Option::is_some));
+        match result {
+            Ok(_) if complete => break,
+            Err(error) if tracer.changes == changes => return Err(error),
+            Ok(_) if tracer.changes == changes => {
+                return Err(TraceError("not every enum variant was reached".to_owned()));
+            }
+            _ => (),
+        }
+    }
+
+    let mut shape = tracer.shape;
+    for (name, variants) in tracer.enums {
+        shape.push_str(&name);
+        shape.push('=');
+        for variant in variants.into_iter().flatten() {
+            shape.push_str(&variant);
+        }
+        shape.push(';');
+    }
+    Ok(shape)
+}
+
+// Deserializer which records the shape of the type being deserialised, feeding it placeholder
+// values.  Every sequence and map is given a single element so that its element type is traced.
+//
+// Only one variant of an enum can be traced per decode, since the visitor is consumed by it, so
+// enums are recorded by name in the shape and their variants traced separately over several
+// decodes.  Within a variant, the same enum is decoded via an already-traced variant, so that
+// recursive enums have a finite shape.
+struct Tracer {
+    shape: String,
+    depth: usize,
+    // Whether placeholders are non-zero and non-empty.
+    nonzero: bool,
+    // The shape of each traced variant of each enum seen so far.
+    enums: BTreeMap<String, Vec<Option<String>>>,
+    // The enums whose variants are currently being traced.
+    tracing: Vec<String>,
+    // Variants which failed to trace since a variant was last traced successfully.
+    failed: HashSet<(String, usize)>,
+    // Incremented whenever a variant is traced or fails to trace.
+    changes: usize,
+}
+
+impl Tracer {
+    fn primitive(&mut self, name: &str) {
+        self.shape.push_str(name);
+        self.shape.push(';');
+    }
+
+    fn nested<T, F>(&mut self, open: &str, trace: F) -> Result<T, TraceError>
+    where
+        F: FnOnce(&mut Tracer) -> Result<T, TraceError>,
+    {
+        if self.depth >= MAX_TRACE_DEPTH {
+            return Err(TraceError(format!(
+                "nesting exceeds {} levels at `{}`",
+                MAX_TRACE_DEPTH, open
+            )));
+        }
+        self.shape.push_str(open);
+        self.shape.push('<');
+        self.depth += 1;
+        let result = trace(self);
+        self.depth -= 1;
+        self.shape.push('>');
+        result
+    }
+
+    fn unrolled(&self) -> bool {
+        self.depth < MAX_UNROLLED_DEPTH
+    }
+
+    // The first variant of `name` which is yet to be traced, unless it is already being traced.
+    fn untraced_variant(&mut self, name: &str, count: usize) -> Option<usize> {
+        if self.tracing.iter().any(|tracing| tracing == name) {
+            return None;
+        }
+        let variants = self
+            .enums
+            .entry(name.to_owned())
+            .or_insert_with(|| vec![None; count]);
+        let failed = &self.failed;
+        (0..count)
+            .find(|&index| variants[index].is_none() && !failed.contains(&(name.to_owned(), index)))
+    }
+
+    fn traced_variant(&self, name: &str) -> Result<usize, TraceError> {
+        self.enums[name]
+            .iter()
+            .position(Option::is_some)
+            .ok_or_else(|| {
+                TraceError(format!(
+                    "no variant of `{}` can be traced without recursing into it",
+                    name
+                ))
+            })
+    }
+}
+
+macro_rules! trace_primitive {
+    ($($method:ident => $visit:ident($zero:expr, $nonzero:expr),)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TraceError> {
+                self.primitive(stringify!($method));
+                visitor.$visit(if self.nonzero { $nonzero } else { $zero })
+            }
+        )*
+    };
+}
+
+impl<'de> Deserializer<'de> for &mut Tracer {
+    type Error = TraceError;
+
+    trace_primitive! {
+        deserialize_bool => visit_bool(false, true),
+        deserialize_i8 => visit_i8(0, 1),
+        deserialize_i16 => visit_i16(0, 1),
+        deserialize_i32 => visit_i32(0, 1),
+        deserialize_i64 => visit_i64(0, 1),
+        deserialize_u8 => visit_u8(0, 1),
+        deserialize_u16 => visit_u16(0, 1),
+        deserialize_u32 => visit_u32(0, 1),
+        deserialize_u64 => visit_u64(0, 1),
+        deserialize_f32 => visit_f32(0.0, 1.0),
+        deserialize_f64 => visit_f64(0.0, 1.0),
+        deserialize_char => visit_char('\0', '1'),
+        deserialize_str => visit_str("", "1"),
+        deserialize_string => visit_string(String::new(), "1".to_owned()),
+        deserialize_bytes => visit_bytes(&[][..], &[1][..]),
+        deserialize_byte_buf => visit_byte_buf(Vec::new(), vec![1]),
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TraceError> {
+        self.primitive("deserialize_unit");
+        visitor.visit_unit()
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TraceError> {
+        self.primitive("deserialize_identifier");
+        visitor.visit_u32(0)
+    }
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, TraceError> {
+        Err(TraceError(
+            "types using `deserialize_any()` have no fixed shape".to_owned(),
+        ))
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TraceError> {
+        self.deserialize_any(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TraceError> {
+        self.nested("option", |tracer| {
+            if tracer.unrolled() {
+                visitor.visit_some(tracer)
+            } else {
+                visitor.visit_none()
+            }
+        })
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, TraceError> {
+        self.primitive(&format!("unit_struct {}", name));
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, TraceError> {
+        self.nested(&format!("newtype_struct {}", name), |tracer| {
+            visitor.visit_newtype_struct(tracer)
+        })
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TraceError> {
+        self.nested("seq", |tracer| {
+            let remaining = if tracer.unrolled() { 1 } else { 0 };
+            visitor.visit_seq(TraceElements { tracer, remaining })
+        })
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, TraceError> {
+        self.nested(&format!("tuple {}", len), |tracer| {
+            visitor.visit_seq(TraceElements {
+                tracer,
+                remaining: len,
+            })
+        })
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, TraceError> {
+        self.nested(&format!("tuple_struct {} {}", name, len), |tracer| {
+            visitor.visit_seq(TraceElements {
+                tracer,
+                remaining: len,
+            })
+        })
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TraceError> {
+        self.nested("map", |tracer| {
+            let remaining = if tracer.unrolled() { 1 } else { 0 };
+            visitor.visit_map(TraceElements { tracer, remaining })
+        })
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, TraceError> {
+        self.nested(
+            &format!("struct {}{{{}}}", name, fields.join(",")),
+            |tracer| {
+                visitor.visit_seq(TraceElements {
+                    tracer,
+                    remaining: fields.len(),
+                })
+            },
+        )
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, TraceError> {
+        let name = format!("enum {}{{{}}}", name, variants.join(","));
+        self.primitive(&name);
+        let untraced = self.untraced_variant(&name, variants.len());
+        let index = match untraced {
+            Some(index) => index,
+            None => self.traced_variant(&name)?,
+        };
+
+        let outer = mem::take(&mut self.shape);
+        if untraced.is_some() {
+            self.tracing.push(name.clone());
+        }
+        let result = self.nested(variants[index], |tracer| {
+            visitor.visit_enum(TraceVariant {
+                tracer,
+                index: index as u32,
+            })
+        });
+        let shape = mem::replace(&mut self.shape, outer);
+        if untraced.is_some() {
+            let _ = self.tracing.pop();
+            self.changes += 1;
+            if result.is_ok() {
+                if let Some(variants) = self.enums.get_mut(&name) {
+                    variants[index] = Some(shape);
+                }
+                self.failed.clear();
+            } else {
+                let _ = self.failed.insert((name, index));
+            }
+        }
+        result
+    }
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+}
+
+struct TraceElements<'a> {
+    tracer: &'a mut Tracer,
+    remaining: usize,
+}
+
+impl<'de, 'a> SeqAccess<'de> for TraceElements<'a> {
+    type Error = TraceError;
+
+    fn next_element_seed<S: DeserializeSeed<'de>>(
+        &mut self,
+        seed: S,
+    ) -> Result<Option<S::Value>, TraceError> {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        self.remaining -= 1;
+        seed.deserialize(&mut *self.tracer).map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.remaining)
+    }
+}
+
+impl<'de, 'a> MapAccess<'de> for TraceElements<'a> {
+    type Error = TraceError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, TraceError> {
+        self.next_element_seed(seed)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, TraceError> {
+        seed.deserialize(&mut *self.tracer)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.remaining)
+    }
+}
+
+struct TraceVariant<'a> {
+    tracer: &'a mut Tracer,
+    index: u32,
+}
+
+impl<'de, 'a> EnumAccess<'de> for TraceVariant<'a> {
+    type Error = TraceError;
+    type Variant = &'a mut Tracer;
+
+    fn variant_seed<S: DeserializeSeed<'de>>(
+        self,
+        seed: S,
+    ) -> Result<(S::Value, &'a mut Tracer), TraceError> {
+        let variant = seed.deserialize(IntoDeserializer::<TraceError>::into_deserializer(
+            self.index,
+        ))?;
+        Ok((variant, self.tracer))
+    }
+}
+
+impl<'de> VariantAccess<'de> for &mut Tracer {
+    type Error = TraceError;
+
+    fn unit_variant(self) -> Result<(), TraceError> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<S: DeserializeSeed<'de>>(
+        self,
+        seed: S,
+    ) -> Result<S::Value, TraceError> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(
+        self,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, TraceError> {
+        self.deserialize_tuple(len, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, TraceError> {
+        self.deserialize_struct("", fields, visitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serialisation::serialise;
+    use serde_derive::{Deserialize, Serialize};
+    use std::collections::BTreeMap;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Ping {
+        id: u64,
+    }
+
+    impl Tagged for Ping {}
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Pong {
+        id: u64,
+    }
+
+    impl Tagged for Pong {}
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Renamed {
+        id: u64,
+    }
+
+    impl Tagged for Renamed {
+        const TYPE_ID: Option<u64> = Some(0x5049_4e47);
+    }
+
+    // A recursive type, which is only traced to a limited depth.
+    #[derive(Serialize, Deserialize)]
+    struct Tree {
+        children: Vec<Tree>,
+        labels: BTreeMap<String, Option<Box<Tree>>>,
+    }
+
+    // A recursive enum, traced by decoding `Literal` within `Negate`.
+    #[derive(Serialize, Deserialize)]
+    enum Expr {
+        Negate(Box<Expr>),
+        Literal(i64),
+    }
+
+    // An enum none of whose variants can be decoded without recursing.
+    #[derive(Serialize, Deserialize)]
+    enum Endless {
+        Again(Box<Endless>),
+    }
+
+    // Enums which differ only in their last variant.
+    mod v1 {
+        use serde_derive::{Deserialize, Serialize};
+
+        #[derive(Serialize, Deserialize)]
+        pub enum Message {
+            Ping,
+            Data(u32),
+        }
+    }
+
+    mod v2 {
+        use serde_derive::{Deserialize, Serialize};
+
+        #[derive(Serialize, Deserialize)]
+        pub enum Message {
+            Ping,
+            Data(u64),
+        }
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct Port(::std::num::NonZeroU16);
+
+    #[test]
+    fn fingerprints() {
+        let ping = unwrap!(type_fingerprint::<Ping>());
+        assert_eq!(ping, unwrap!(type_fingerprint::<Ping>()));
+        assert_ne!(ping, unwrap!(type_fingerprint::<Pong>()));
+        assert_ne!(ping, unwrap!(type_fingerprint::<u64>()));
+        assert_ne!(
+            unwrap!(type_fingerprint::<Vec<u32>>()),
+            unwrap!(type_fingerprint::<Vec<i32>>())
+        );
+        assert_ne!(
+            unwrap!(type_fingerprint::<Option<String>>()),
+            unwrap!(type_fingerprint::<Vec<u8>>())
+        );
+        // Types which serde sees identically share a fingerprint.
+        assert_eq!(
+            unwrap!(type_fingerprint::<(u8, u8)>()),
+            unwrap!(type_fingerprint::<[u8; 2]>())
+        );
+        let _ = unwrap!(type_fingerprint::<Tree>());
+        let _ = unwrap!(type_fingerprint::<Expr>());
+        assert_ne!(
+            unwrap!(type_fingerprint::<v1::Message>()),
+            unwrap!(type_fingerprint::<v2::Message>())
+        );
+        assert_ne!(
+            unwrap!(type_fingerprint::<Port>()),
+            unwrap!(type_fingerprint::<u16>())
+        );
+        match unwrap_err!(type_fingerprint::<Endless>()) {
+            SerialisationError::TypeFingerprint(_) => (),
+            err => panic!("{:?}", err),
+        }
+        match unwrap_err!(type_fingerprint::<::serde_value::Value>()) {
+            SerialisationError::TypeFingerprint(_) => (),
+            err => panic!("{:?}", err),
+        }
+    }
+
+    #[test]
+    fn tagged_round_trip() {
+        let ping = Ping { id: 3 };
+        let serialised = unwrap!(serialise_tagged(&ping));
+        assert_eq!(&serialised[TYPE_TAG_LEN..], &unwrap!(serialise(&ping))[..]);
+        assert_eq!(
+            unwrap!(tagged_type(&serialised)),
+            unwrap!(type_fingerprint::<Ping>())
+        );
+        assert_eq!(unwrap!(deserialise_tagged::<Ping>(&serialised)), ping);
+        assert_eq!(
+            unwrap!(deserialise_tagged_with_limit::<Ping>(
+                &serialised,
+                Bounded(8)
+            )),
+            ping
+        );
+
+        // A type with the same encoding is still rejected.
+        match unwrap_err!(deserialise_tagged::<Pong>(&serialised)) {
+            SerialisationError::TypeMismatch(expected, found) => {
+                assert_eq!(expected, unwrap!(type_fingerprint::<Pong>()));
+                assert_eq!(found, unwrap!(type_fingerprint::<Ping>()));
+            }
+            err => panic!("{:?}", err),
+        }
+
+        let renamed = Renamed { id: 3 };
+        let serialised = unwrap!(serialise_tagged_with_limit(&renamed, Bounded(8)));
+        assert_eq!(unwrap!(tagged_type(&serialised)), 0x5049_4e47);
+        assert_eq!(unwrap!(deserialise_tagged::<Renamed>(&serialised)), renamed);
+        match unwrap_err!(deserialise_tagged::<Ping>(&serialised)) {
+            SerialisationError::TypeMismatch(_, 0x5049_4e47) => (),
+            err => panic!("{:?}", err),
+        }
+
+        match unwrap_err!(deserialise_tagged::<Ping>(&serialised[..4])) {
+            SerialisationError::Deserialise(ErrorKind::IoError(_), None) => (),
+            err => panic!("{:?}", err),
+        }
+    }
+}
@@ -0,0 +1,182 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// https://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+//! Helpers for testing the serialisation of downstream types.
+
+use super::{serialise_canonical, serialised_size, Bincode, Format};
+use rand::{Rand, Rng};
+use serde::de::DeserializeOwned;
+use serde::ser::Serialize;
+use std::env;
+use std::fmt::Debug;
+use std::fs;
+use std::path::Path;
+
+/// Setting this environment variable makes [`assert_golden()`](fn.assert_golden.html) write golden
+/// files, creating missing ones and overwriting existing ones, rather than comparing against them.
+pub const UPDATE_GOLDEN_ENV_VAR: &str = "UPDATE_GOLDEN_FILES";
+
+/// Asserts that `value` survives a round trip through bincode, and that
+/// [`serialised_size()`](../fn.serialised_size.html) agrees with the length of the serialised
+/// data.
+pub fn assert_round_trip<T>(value: &T)
+where
+    T: Serialize + DeserializeOwned + PartialEq + Debug,
+{
+    let serialised = unwrap!(Bincode::serialise(value, None));
+    assert_eq!(
+        serialised.len() as u64,
+        serialised_size(value),
+        "serialised_size() is wrong for {:?}",
+        value
+    );
+    let decoded: T = unwrap!(Bincode::deserialise(&serialised, None));
+    assert_eq!(*value, decoded);
+}
+
+/// Runs [`assert_round_trip()`](fn.assert_round_trip.html) on `iterations` values generated by
+/// `rng`.
+///
+/// Pass a [`SeededRng`](../../struct.SeededRng.html) so that the seed is printed if a check fails.
+pub fn assert_random_round_trips<T, R>(rng: &mut R, iterations: usize)
+where
+    T: Rand + Serialize + DeserializeOwned + PartialEq + Debug,
+    R: Rng,
+{
+    for _ in 0..iterations {
+        assert_round_trip(&rng.gen::<T>());
+    }
+}
+
+/// Asserts that the serialised form of `value` matches the bytes stored in the golden file at
+/// `path`, and that those bytes still deserialise to `value`.
+///
+/// A missing golden file fails the assertion.  Run the tests with the `UPDATE_GOLDEN_FILES`
+/// environment variable set to write it (or to overwrite an existing one) instead.  Golden files
+/// should be committed alongside the tests which use them, so that an unintended change to a
+/// type's wire format fails the test.
+pub fn assert_golden<T, P>(path: P, value: &T)
+where
+    T: Serialize + DeserializeOwned + PartialEq + Debug,
+    P: AsRef<Path>,
+{
+    assert_golden_impl(
+        path.as_ref(),
+        value,
+        env::var_os(UPDATE_GOLDEN_ENV_VAR).is_some(),
+    )
+}
+
+fn assert_golden_impl<T>(path: &Path, value: &T, update: bool)
+where
+    T: Serialize + DeserializeOwned + PartialEq + Debug,
+{
+    let serialised = unwrap!(Bincode::serialise(value, None));
+    if update {
+        if let Some(dir) = path.parent() {
+            unwrap!(fs::create_dir_all(dir));
+        }
+        unwrap!(fs::write(path, &serialised));
+        return;
+    }
+    if !path.exists() {
+        panic!(
+            "golden file {} for {:?} doesn't exist.  Set {} to create it.",
+            path.display(),
+            value,
+            UPDATE_GOLDEN_ENV_VAR
+        );
+    }
+
+    let golden = unwrap!(fs::read(path));
+    if golden != serialised {
+        let offset = golden
+            .iter()
+            .zip(&serialised)
+            .position(|(lhs, rhs)| lhs != rhs)
+            .unwrap_or_else(|| golden.len().min(serialised.len()));
+        panic!(
+            "wire format of {:?} no longer matches golden file {}: {} bytes expected, {} bytes \
+             produced, first difference at byte {}.  Set {} to update the file if this change is \
+             intended.",
+            value,
+            path.display(),
+            golden.len(),
+            serialised.len(),
+            offset,
+            UPDATE_GOLDEN_ENV_VAR
+        );
+    }
+    let decoded: T = unwrap!(Bincode::deserialise(&golden, None));
+    assert_eq!(*value, decoded);
+}
+
+/// Asserts that `value` survives a round trip through
+/// [`serialise_canonical()`](../fn.serialise_canonical.html) and that re-serialising the decoded
+/// value produces identical canonical bytes.
+pub fn assert_canonical_round_trip<T>(value: &T)
+where
+    T: Serialize + DeserializeOwned + PartialEq + Debug,
+{
+    let canonical = unwrap!(serialise_canonical(value));
+    let decoded: T = unwrap!(Bincode::deserialise(&canonical, None));
+    assert_eq!(*value, decoded);
+    assert_eq!(
+        canonical,
+        unwrap!(serialise_canonical(&decoded)),
+        "canonical bytes changed after a round trip of {:?}",
+        value
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serialisation::serialise;
+    use rand::{SeedableRng, XorShiftRng};
+    use std::panic;
+    use std::process;
+
+    #[test]
+    fn round_trips() {
+        assert_round_trip(&(vec![0u8, 1, 3, 9], "SomeString".to_string()));
+
+        // `SeededRng` would share the process-wide seed with other tests, so use a plain RNG here.
+        let mut rng = XorShiftRng::from_seed([1, 2, 3, 4]);
+        assert_random_round_trips::<(u64, i8, Option<bool>, [u16; 4]), _>(&mut rng, 100);
+        assert_random_round_trips::<(char, f64), _>(&mut rng, 100);
+    }
+
+    #[test]
+    fn golden_file() {
+        let dir = env::temp_dir().join(format!("maidsafe_utilities_golden_{}", process::id()));
+        let path = dir.join("fixture.bin");
+
+        // A missing file fails rather than being written, unless updating.
+        let value = (7u32, "seven".to_owned());
+        assert!(panic::catch_unwind(|| assert_golden(&path, &value)).is_err());
+        assert!(!path.exists());
+        assert_golden_impl(&path, &value, true);
+        assert_eq!(
+            unwrap!(fs::read(&path)),
+            unwrap!(serialise(&(7u32, "seven")))
+        );
+        assert_golden(&path, &value);
+
+        // Changing the value or its type changes the wire format.
+        for result in &[
+            panic::catch_unwind(|| assert_golden(&path, &(8u32, "seven".to_owned()))),
+            panic::catch_unwind(|| assert_golden(&path, &(7u64, "seven".to_owned()))),
+        ] {
+            assert!(result.is_err());
+        }
+
+        unwrap!(fs::remove_dir_all(&dir));
+    }
+}
@@ -0,0 +1,908 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// https://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+use super::limits::DeserialiseLimits;
+use super::SerialisationError;
+use bincode::ErrorKind;
+use serde::de::{
+    self, DeserializeSeed, Deserializer, EnumAccess, MapAccess, SeqAccess, VariantAccess, Visitor,
+};
+use std::any;
+use std::cell::{Cell, RefCell};
+use std::fmt::{self, Display, Formatter};
+
+/// Where in the input a deserialisation error occurred.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ErrorContext {
+    offset: u64,
+    type_name: &'static str,
+    path: String,
+}
+
+impl ErrorContext {
+    /// Number of input bytes which had been consumed when the error occurred.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Name of the type being decoded when the error occurred, as given by
+    /// `std::any::type_name()`.
+    pub fn type_name(&self) -> &'static str {
+        self.type_name
+    }
+
+    /// Fields, elements and enum variants leading to the value being decoded, e.g.
+    /// `Message.body.entries[3].name`.  Empty if the error occurred at the top level, or if decoding
+    /// from a stream, where only the offset and top-level type are known.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+}
+
+impl Display for ErrorContext {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        write!(
+            formatter,
+            "byte {} while decoding `{}`",
+            self.offset, self.type_name
+        )?;
+        if !self.path.is_empty() {
+            write!(formatter, " at `{}`", self.path)?;
+        }
+        Ok(())
+    }
+}
+
+pub enum Segment {
+    Field(&'static str),
+    Index(usize),
+    Variant(&'static str),
+    MapKey(usize),
+    MapValue(usize),
+}
+
+impl Display for Segment {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        match *self {
+            Segment::Field(name) => write!(formatter, ".{}", name),
+            Segment::Index(index) => write!(formatter, "[{}]", index),
+            Segment::Variant(name) => write!(formatter, "::{}", name),
+            Segment::MapKey(index) => write!(formatter, "[{}].key", index),
+            Segment::MapValue(index) => write!(formatter, "[{}].value", index),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Exceeded {
+    CollectionLength(u64),
+    Depth,
+}
+
+/// A value decoded while annotating, with the range of input bytes it was decoded from.
+pub struct Annotation {
+    pub start: usize,
+    pub end: usize,
+    pub path: String,
+    pub type_name: &'static str,
+    pub value: String,
+}
+
+/// What is recorded while decoding through the tracked wrappers.
+///
+/// `Limiter` only enforces the collection length and nesting depth limits, so is cheap enough to
+/// use for every decode.  `Tracker` also records the field path and type of whatever is being
+/// decoded, so that the context of an error can be reported.
+pub trait Track {
+    /// Decodes a value one level further down, failing if that would exceed the maximum depth.
+    fn descend<T, E: de::Error, F: FnOnce() -> Result<T, E>>(&self, f: F) -> Result<T, E>;
+
+    /// Fails if a collection of `len` elements would exceed the maximum collection length.
+    fn check_len<E: de::Error>(&self, len: Option<usize>) -> Result<(), E>;
+
+    /// Decodes a value of type `V` produced by a seed.  `record_type` is false for values which
+    /// aren't reported as the type being decoded.
+    fn seed<V: ?Sized, T, E, F: FnOnce() -> Result<T, E>>(
+        &self,
+        record_type: bool,
+        f: F,
+    ) -> Result<T, E>;
+
+    /// Called on entering a named type.
+    fn enter_named(&self, _name: &'static str) {}
+
+    /// Decodes the part of a value identified by `segment`.
+    fn within<R, F: FnOnce() -> R>(&self, _segment: Segment, f: F) -> R {
+        f()
+    }
+
+    /// Records the context of `result` if it's an error.
+    fn track<T, E>(&self, result: Result<T, E>) -> Result<T, E> {
+        result
+    }
+
+    /// Records the input consumed by the current value, if annotating.
+    fn annotate<F: FnOnce() -> String>(&self, _value: F) {}
+
+    /// Records the index of the enum variant being decoded.
+    fn set_variant_index(&self, _index: Option<u64>) {}
+
+    /// Returns the index of the enum variant being decoded, if known.
+    fn variant_index(&self) -> Option<u64> {
+        None
+    }
+}
+
+/// Enforces the collection length and nesting depth limits, without recording any context.
+pub struct Limiter {
+    max_collection_len: u64,
+    max_depth: usize,
+    depth: Cell<usize>,
+    exceeded: Cell<Option<Exceeded>>,
+}
+
+impl Limiter {
+    pub fn new(limits: &DeserialiseLimits) -> Self {
+        Limiter {
+            max_collection_len: limits.max_collection_len,
+            max_depth: limits.max_depth,
+            depth: Cell::new(0),
+            exceeded: Cell::new(None),
+        }
+    }
+
+    /// Returns whether decoding failed because a limit was exceeded.
+    #[cfg(any(feature = "json", feature = "msgpack", feature = "cbor"))]
+    pub fn is_exceeded(&self) -> bool {
+        self.exceeded.get().is_some()
+    }
+
+    /// Converts the error which caused the decode to fail into a `SerialisationError` with the
+    /// given context.
+    pub fn into_error(self, error: ErrorKind, context: Option<ErrorContext>) -> SerialisationError {
+        match self.exceeded.get() {
+            Some(Exceeded::CollectionLength(len)) => {
+                SerialisationError::CollectionTooLong(len, self.max_collection_len, context)
+            }
+            Some(Exceeded::Depth) => SerialisationError::NestingTooDeep(self.max_depth, context),
+            None => SerialisationError::Deserialise(error, context),
+        }
+    }
+}
+
+impl Track for Limiter {
+    fn descend<T, E: de::Error, F: FnOnce() -> Result<T, E>>(&self, f: F) -> Result<T, E> {
+        let depth = self.depth.get();
+        if depth >= self.max_depth {
+            self.exceeded.set(Some(Exceeded::Depth));
+            return Err(E::custom("maximum nesting depth exceeded"));
+        }
+        self.depth.set(depth + 1);
+        let result = f();
+        self.depth.set(depth);
+        result
+    }
+
+    fn check_len<E: de::Error>(&self, len: Option<usize>) -> Result<(), E> {
+        match len {
+            Some(len) if len as u64 > self.max_collection_len => {
+                self.exceeded
+                    .set(Some(Exceeded::CollectionLength(len as u64)));
+                Err(E::custom("maximum collection length exceeded"))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn seed<V: ?Sized, T, E, F: FnOnce() -> Result<T, E>>(
+        &self,
+        _record_type: bool,
+        f: F,
+    ) -> Result<T, E> {
+        let result = f();
+        // If the value decoded successfully, any limit hit while decoding it was recovered from.
+        if result.is_ok() {
+            self.exceeded.set(None);
+        }
+        result
+    }
+}
+
+/// Returns the context of an error for which only the offset and top-level type are known.
+pub fn top_level_context(offset: usize, type_name: &'static str) -> ErrorContext {
+    ErrorContext {
+        offset: offset as u64,
+        type_name,
+        path: String::new(),
+    }
+}
+
+/// Enforces the limits like `Limiter`, and also records the current field path, type and depth,
+/// and the context of the innermost error seen so far.
+pub struct Tracker<'a> {
+    position: &'a dyn Fn() -> usize,
+    limiter: Limiter,
+    root: Cell<Option<&'static str>>,
+    path: RefCell<Vec<Segment>>,
+    types: RefCell<Vec<&'static str>>,
+    variant_index: Cell<Option<u64>>,
+    context: RefCell<Option<ErrorContext>>,
+    start: Cell<usize>,
+    annotations: Option<RefCell<Vec<Annotation>>>,
+}
+
+impl<'a> Tracker<'a> {
+    /// `position` should return the number of input bytes consumed so far.  The byte limit is
+    /// enforced by bincode rather than here.
+    pub fn new(position: &'a dyn Fn() -> usize, limits: &DeserialiseLimits) -> Self {
+        Tracker {
+            position,
+            limiter: Limiter::new(limits),
+            root: Cell::new(None),
+            path: RefCell::new(Vec::new()),
+            types: RefCell::new(Vec::new()),
+            variant_index: Cell::new(None),
+            context: RefCell::new(None),
+            start: Cell::new(0),
+            annotations: None,
+        }
+    }
+
+    /// As `new()`, but also records an `Annotation` for every value which consumes input.
+    pub fn annotating(position: &'a dyn Fn() -> usize, limits: &DeserialiseLimits) -> Self {
+        Tracker {
+            annotations: Some(RefCell::new(Vec::new())),
+            ..Tracker::new(position, limits)
+        }
+    }
+
+    /// Returns the annotations recorded so far.
+    pub fn take_annotations(&self) -> Vec<Annotation> {
+        self.annotations
+            .as_ref()
+            .map(|annotations| annotations.replace(Vec::new()))
+            .unwrap_or_default()
+    }
+
+    /// Converts the error which caused the decode to fail into a `SerialisationError`, adding the
+    /// context in which it occurred.
+    pub fn into_error(self, error: ErrorKind) -> SerialisationError {
+        let context = self.context.into_inner();
+        self.limiter.into_error(error, context)
+    }
+
+    fn current_path(&self) -> String {
+        let mut path = self.root.get().unwrap_or("").to_owned();
+        for segment in self.path.borrow().iter() {
+            path.push_str(&segment.to_string());
+        }
+        path
+    }
+
+    fn type_name(&self) -> &'static str {
+        self.types.borrow().last().cloned().unwrap_or("")
+    }
+}
+
+impl<'a> Track for Tracker<'a> {
+    fn descend<T, E: de::Error, F: FnOnce() -> Result<T, E>>(&self, f: F) -> Result<T, E> {
+        let result = self.limiter.descend(|| {
+            self.start.set((self.position)());
+            f()
+        });
+        self.track(result)
+    }
+
+    fn check_len<E: de::Error>(&self, len: Option<usize>) -> Result<(), E> {
+        self.limiter.check_len(len)
+    }
+
+    fn seed<V: ?Sized, T, E, F: FnOnce() -> Result<T, E>>(
+        &self,
+        record_type: bool,
+        f: F,
+    ) -> Result<T, E> {
+        if record_type {
+            self.types.borrow_mut().push(any::type_name::<V>());
+        }
+        let result = self.limiter.seed::<V, _, _, _>(record_type, f);
+        if record_type {
+            let _ = self.types.borrow_mut().pop();
+        }
+        // If the value decoded successfully, any error recorded while decoding it was recovered
+        // from.
+        if result.is_ok() {
+            let _ = self.context.borrow_mut().take();
+        }
+        result
+    }
+
+    // The first named type entered becomes the start of the path.
+    fn enter_named(&self, name: &'static str) {
+        if self.root.get().is_none() && self.path.borrow().is_empty() {
+            self.root.set(Some(name));
+        }
+    }
+
+    fn within<R, F: FnOnce() -> R>(&self, segment: Segment, f: F) -> R {
+        self.path.borrow_mut().push(segment);
+        let result = f();
+        let _ = self.path.borrow_mut().pop();
+        result
+    }
+
+    // Errors propagate outwards, so only the first (innermost) one is recorded.
+    fn track<T, E>(&self, result: Result<T, E>) -> Result<T, E> {
+        if result.is_err() {
+            let mut context = self.context.borrow_mut();
+            if context.is_none() {
+                *context = Some(ErrorContext {
+                    offset: (self.position)() as u64,
+                    type_name: self.type_name(),
+                    path: self.current_path(),
+                });
+            }
+        }
+        result
+    }
+
+    // Values are annotated before any values nested within them are decoded.
+    fn annotate<F: FnOnce() -> String>(&self, value: F) {
+        if let Some(ref annotations) = self.annotations {
+            let start = self.start.get();
+            let end = (self.position)();
+            if end > start {
+                annotations.borrow_mut().push(Annotation {
+                    start,
+                    end,
+                    path: self.current_path(),
+                    type_name: self.type_name(),
+                    value: value(),
+                });
+            }
+        }
+    }
+
+    fn set_variant_index(&self, index: Option<u64>) {
+        self.variant_index.set(index);
+    }
+
+    fn variant_index(&self) -> Option<u64> {
+        self.variant_index.get()
+    }
+}
+
+/// Wraps a `DeserializeSeed` so that the value it produces is decoded through a
+/// `TrackedDeserializer`.
+pub struct TrackedSeed<'a, S, K> {
+    seed: S,
+    tracker: &'a K,
+    record_type: bool,
+}
+
+impl<'a, S, K: Track> TrackedSeed<'a, S, K> {
+    pub fn new(seed: S, tracker: &'a K) -> Self {
+        TrackedSeed {
+            seed,
+            tracker,
+            record_type: true,
+        }
+    }
+
+    // Variant identifiers are an implementation detail of the enum, so aren't reported as the
+    // type being decoded.
+    fn identifier(seed: S, tracker: &'a K) -> Self {
+        TrackedSeed {
+            seed,
+            tracker,
+            record_type: false,
+        }
+    }
+}
+
+impl<'a, 'de, S: DeserializeSeed<'de>, K: Track> DeserializeSeed<'de> for TrackedSeed<'a, S, K> {
+    type Value = S::Value;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<S::Value, D::Error> {
+        let seed = self.seed;
+        let tracker = self.tracker;
+        tracker.seed::<S::Value, _, _, _>(self.record_type, move || {
+            seed.deserialize(TrackedDeserializer::new(deserializer, tracker))
+        })
+    }
+}
+
+/// Wraps a `Deserializer`, recording the field path and type of whatever is being decoded.
+pub struct TrackedDeserializer<'a, D, K> {
+    inner: D,
+    tracker: &'a K,
+}
+
+impl<'a, D, K: Track> TrackedDeserializer<'a, D, K> {
+    fn new(inner: D, tracker: &'a K) -> Self {
+        TrackedDeserializer { inner, tracker }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Kind {
+    Plain,
+    Struct(&'static [&'static str]),
+    Enum(&'static [&'static str]),
+    Identifier,
+}
+
+macro_rules! forward_deserialize {
+    ($($method:ident),*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, D::Error> {
+                let tracker = self.tracker;
+                tracker.descend(move ||
+                    self.inner
+                        .$method(TrackedVisitor::new(visitor, tracker, Kind::Plain)),
+                )
+            }
+        )*
+    };
+}
+
+impl<'a, 'de, D: Deserializer<'de>, K: Track> Deserializer<'de> for TrackedDeserializer<'a, D, K> {
+    type Error = D::Error;
+
+    forward_deserialize!(
+        deserialize_any,
+        deserialize_bool,
+        deserialize_i8,
+        deserialize_i16,
+        deserialize_i32,
+        deserialize_i64,
+        deserialize_i128,
+        deserialize_u8,
+        deserialize_u16,
+        deserialize_u32,
+        deserialize_u64,
+        deserialize_u128,
+        deserialize_f32,
+        deserialize_f64,
+        deserialize_char,
+        deserialize_str,
+        deserialize_string,
+        deserialize_bytes,
+        deserialize_byte_buf,
+        deserialize_option,
+        deserialize_unit,
+        deserialize_seq,
+        deserialize_map,
+        deserialize_ignored_any
+    );
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, D::Error> {
+        let tracker = self.tracker;
+        tracker.enter_named(name);
+        tracker.descend(move || {
+            self.inner
+                .deserialize_unit_struct(name, TrackedVisitor::new(visitor, tracker, Kind::Plain))
+        })
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, D::Error> {
+        let tracker = self.tracker;
+        tracker.enter_named(name);
+        tracker.descend(move || {
+            self.inner.deserialize_newtype_struct(
+                name,
+                TrackedVisitor::new(visitor, tracker, Kind::Plain),
+            )
+        })
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, D::Error> {
+        let tracker = self.tracker;
+        tracker.descend(move || {
+            self.inner
+                .deserialize_tuple(len, TrackedVisitor::new(visitor, tracker, Kind::Plain))
+        })
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, D::Error> {
+        let tracker = self.tracker;
+        tracker.enter_named(name);
+        tracker.descend(move || {
+            self.inner.deserialize_tuple_struct(
+                name,
+                len,
+                TrackedVisitor::new(visitor, tracker, Kind::Plain),
+            )
+        })
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, D::Error> {
+        let tracker = self.tracker;
+        tracker.enter_named(name);
+        tracker.descend(move || {
+            self.inner.deserialize_struct(
+                name,
+                fields,
+                TrackedVisitor::new(visitor, tracker, Kind::Struct(fields)),
+            )
+        })
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, D::Error> {
+        let tracker = self.tracker;
+        tracker.enter_named(name);
+        tracker.descend(move || {
+            self.inner.deserialize_enum(
+                name,
+                variants,
+                TrackedVisitor::new(visitor, tracker, Kind::Enum(variants)),
+            )
+        })
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, D::Error> {
+        // Identifiers name a field or variant of the enclosing value rather than being nested in it.
+        let tracker = self.tracker;
+        tracker.track(self.inner.deserialize_identifier(TrackedVisitor::new(
+            visitor,
+            tracker,
+            Kind::Identifier,
+        )))
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.inner.is_human_readable()
+    }
+}
+
+struct TrackedVisitor<'a, V, K> {
+    inner: V,
+    tracker: &'a K,
+    kind: Kind,
+}
+
+impl<'a, V, K: Track> TrackedVisitor<'a, V, K> {
+    fn new(inner: V, tracker: &'a K, kind: Kind) -> Self {
+        TrackedVisitor {
+            inner,
+            tracker,
+            kind,
+        }
+    }
+}
+
+// How a decoded scalar is shown in annotations.
+trait Describe {
+    fn describe(&self) -> String;
+}
+
+macro_rules! describe_with_debug {
+    ($($ty:ty),*) => {
+        $(
+            impl Describe for $ty {
+                fn describe(&self) -> String {
+                    format!("{:?}", self)
+                }
+            }
+        )*
+    };
+}
+
+describe_with_debug!(bool, i8, i16, i32, i64, i128, u8, u16, u32, u64, u128, f32, f64, char, str);
+
+impl Describe for [u8] {
+    fn describe(&self) -> String {
+        format!("{} bytes", self.len())
+    }
+}
+
+macro_rules! forward_visit {
+    ($($method:ident($ty:ty)),*) => {
+        $(
+            fn $method<E: de::Error>(self, value: $ty) -> Result<V::Value, E> {
+                self.tracker.annotate(|| value.describe());
+                self.inner.$method(value)
+            }
+        )*
+    };
+}
+
+impl<'a, 'de, V: Visitor<'de>, K: Track> Visitor<'de> for TrackedVisitor<'a, V, K> {
+    type Value = V::Value;
+
+    fn expecting(&self, formatter: &mut Formatter) -> fmt::Result {
+        self.inner.expecting(formatter)
+    }
+
+    forward_visit!(
+        visit_bool(bool),
+        visit_i8(i8),
+        visit_i16(i16),
+        visit_i32(i32),
+        visit_i64(i64),
+        visit_i128(i128),
+        visit_u8(u8),
+        visit_u16(u16),
+        visit_u128(u128),
+        visit_f32(f32),
+        visit_f64(f64),
+        visit_char(char),
+        visit_str(&str),
+        visit_borrowed_str(&'de str),
+        visit_string(String),
+        visit_bytes(&[u8]),
+        visit_borrowed_bytes(&'de [u8]),
+        visit_byte_buf(Vec<u8>)
+    );
+
+    fn visit_u32<E: de::Error>(self, value: u32) -> Result<V::Value, E> {
+        if let Kind::Identifier = self.kind {
+            self.tracker.set_variant_index(Some(u64::from(value)));
+        } else {
+            self.tracker.annotate(|| value.describe());
+        }
+        self.inner.visit_u32(value)
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<V::Value, E> {
+        if let Kind::Identifier = self.kind {
+            self.tracker.set_variant_index(Some(value));
+        } else {
+            self.tracker.annotate(|| value.describe());
+        }
+        self.inner.visit_u64(value)
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<V::Value, E> {
+        self.tracker.annotate(|| "None".to_owned());
+        self.inner.visit_none()
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<V::Value, E> {
+        self.inner.visit_unit()
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<V::Value, D::Error> {
+        self.tracker.annotate(|| "Some".to_owned());
+        self.inner
+            .visit_some(TrackedDeserializer::new(deserializer, self.tracker))
+    }
+
+    fn visit_newtype_struct<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<V::Value, D::Error> {
+        self.inner
+            .visit_newtype_struct(TrackedDeserializer::new(deserializer, self.tracker))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<V::Value, A::Error> {
+        self.tracker.check_len(seq.size_hint())?;
+        self.tracker
+            .annotate(|| format!("length {}", seq.size_hint().unwrap_or(0)));
+        let fields = match self.kind {
+            Kind::Struct(fields) => fields,
+            _ => &[],
+        };
+        self.inner.visit_seq(TrackedSeqAccess {
+            inner: seq,
+            tracker: self.tracker,
+            fields,
+            index: 0,
+        })
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<V::Value, A::Error> {
+        self.tracker.check_len(map.size_hint())?;
+        self.tracker
+            .annotate(|| format!("length {}", map.size_hint().unwrap_or(0)));
+        self.inner.visit_map(TrackedMapAccess {
+            inner: map,
+            tracker: self.tracker,
+            index: 0,
+        })
+    }
+
+    fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<V::Value, A::Error> {
+        let variants = match self.kind {
+            Kind::Enum(variants) => variants,
+            _ => &[],
+        };
+        self.inner.visit_enum(TrackedEnumAccess {
+            inner: data,
+            tracker: self.tracker,
+            variants,
+        })
+    }
+}
+
+struct TrackedSeqAccess<'a, A, K> {
+    inner: A,
+    tracker: &'a K,
+    fields: &'static [&'static str],
+    index: usize,
+}
+
+impl<'a, 'de, A: SeqAccess<'de>, K: Track> SeqAccess<'de> for TrackedSeqAccess<'a, A, K> {
+    type Error = A::Error;
+
+    fn next_element_seed<S: DeserializeSeed<'de>>(
+        &mut self,
+        seed: S,
+    ) -> Result<Option<S::Value>, A::Error> {
+        // Formats which don't give the length up front are checked as the elements are read.
+        if self.inner.size_hint().is_none() {
+            self.tracker.check_len(Some(self.index + 1))?;
+        }
+        let segment = match self.fields.get(self.index) {
+            Some(field) => Segment::Field(field),
+            None => Segment::Index(self.index),
+        };
+        self.index += 1;
+        let tracker = self.tracker;
+        let inner = &mut self.inner;
+        tracker.within(segment, || {
+            inner.next_element_seed(TrackedSeed::new(seed, tracker))
+        })
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.inner.size_hint()
+    }
+}
+
+struct TrackedMapAccess<'a, A, K> {
+    inner: A,
+    tracker: &'a K,
+    index: usize,
+}
+
+impl<'a, 'de, A: MapAccess<'de>, K: Track> MapAccess<'de> for TrackedMapAccess<'a, A, K> {
+    type Error = A::Error;
+
+    fn next_key_seed<S: DeserializeSeed<'de>>(
+        &mut self,
+        seed: S,
+    ) -> Result<Option<S::Value>, A::Error> {
+        if self.inner.size_hint().is_none() {
+            self.tracker.check_len(Some(self.index + 1))?;
+        }
+        let tracker = self.tracker;
+        let inner = &mut self.inner;
+        tracker.within(Segment::MapKey(self.index), || {
+            inner.next_key_seed(TrackedSeed::new(seed, tracker))
+        })
+    }
+
+    fn next_value_seed<S: DeserializeSeed<'de>>(&mut self, seed: S) -> Result<S::Value, A::Error> {
+        let tracker = self.tracker;
+        let inner = &mut self.inner;
+        let index = self.index;
+        self.index += 1;
+        tracker.within(Segment::MapValue(index), || {
+            inner.next_value_seed(TrackedSeed::new(seed, tracker))
+        })
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.inner.size_hint()
+    }
+}
+
+struct TrackedEnumAccess<'a, A, K> {
+    inner: A,
+    tracker: &'a K,
+    variants: &'static [&'static str],
+}
+
+impl<'a, 'de, A: EnumAccess<'de>, K: Track> EnumAccess<'de> for TrackedEnumAccess<'a, A, K> {
+    type Error = A::Error;
+    type Variant = TrackedVariantAccess<'a, A::Variant, K>;
+
+    fn variant_seed<S: DeserializeSeed<'de>>(
+        self,
+        seed: S,
+    ) -> Result<(S::Value, Self::Variant), A::Error> {
+        let tracker = self.tracker;
+        let variants = self.variants;
+        tracker.set_variant_index(None);
+        let (value, variant) = self
+            .inner
+            .variant_seed(TrackedSeed::identifier(seed, tracker))?;
+        let name = tracker
+            .variant_index()
+            .and_then(|index| variants.get(index as usize))
+            .cloned()
+            .unwrap_or("");
+        tracker.annotate(|| name.to_owned());
+        Ok((
+            value,
+            TrackedVariantAccess {
+                inner: variant,
+                tracker,
+                name,
+            },
+        ))
+    }
+}
+
+struct TrackedVariantAccess<'a, A, K> {
+    inner: A,
+    tracker: &'a K,
+    name: &'static str,
+}
+
+impl<'a, 'de, A: VariantAccess<'de>, K: Track> VariantAccess<'de>
+    for TrackedVariantAccess<'a, A, K>
+{
+    type Error = A::Error;
+
+    fn unit_variant(self) -> Result<(), A::Error> {
+        self.inner.unit_variant()
+    }
+
+    fn newtype_variant_seed<S: DeserializeSeed<'de>>(self, seed: S) -> Result<S::Value, A::Error> {
+        let tracker = self.tracker;
+        let inner = self.inner;
+        tracker.within(Segment::Variant(self.name), || {
+            tracker.track(inner.newtype_variant_seed(TrackedSeed::new(seed, tracker)))
+        })
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, A::Error> {
+        let tracker = self.tracker;
+        let inner = self.inner;
+        tracker.within(Segment::Variant(self.name), || {
+            tracker
+                .track(inner.tuple_variant(len, TrackedVisitor::new(visitor, tracker, Kind::Plain)))
+        })
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, A::Error> {
+        let tracker = self.tracker;
+        let inner = self.inner;
+        tracker.within(Segment::Variant(self.name), || {
+            tracker.track(inner.struct_variant(
+                fields,
+                TrackedVisitor::new(visitor, tracker, Kind::Struct(fields)),
+            ))
+        })
+    }
+}
@@ -0,0 +1,243 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// https://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+use super::{Bincode, Format, SerialisationError};
+use bincode::Bounded;
+use serde::de::DeserializeOwned;
+use serde::ser::Serialize;
+use std::collections::BTreeMap;
+
+/// Magic bytes written at the start of every versioned envelope.
+pub const VERSIONED_MAGIC: [u8; 4] = [0x4d, 0x53, 0x56, 0x45];
+
+/// Length of the envelope header: the magic bytes followed by a little-endian `u32` version.
+const HEADER_LEN: usize = 8;
+
+type Upgrade<T> = Box<dyn Fn(&[u8], Option<Bounded>) -> Result<T, SerialisationError>>;
+
+/// A type which can be written inside a versioned envelope via
+/// [`serialise_versioned()`](fn.serialise_versioned.html).
+///
+/// Payloads written with an older schema version are decoded using the upgrade functions registered
+/// in [`migrations()`](#method.migrations).
+pub trait Versioned: Serialize + DeserializeOwned {
+    /// The schema version written by `serialise_versioned()`.
+    const VERSION: u32;
+
+    /// Registers upgrade functions from older schema versions.  By default, no older versions are
+    /// supported.
+    fn migrations(migrations: Migrations<Self>) -> Migrations<Self> {
+        migrations
+    }
+}
+
+/// Collection of upgrade functions from older schema versions of `T`.
+pub struct Migrations<T> {
+    upgrades: BTreeMap<u32, Upgrade<T>>,
+}
+
+impl<T: 'static> Migrations<T> {
+    fn new() -> Self {
+        Migrations {
+            upgrades: BTreeMap::new(),
+        }
+    }
+
+    /// Registers `upgrade` to convert a payload written as `Old` at schema `version` into `T`.
+    ///
+    /// Registering the same version twice replaces the earlier upgrade function.
+    pub fn register<Old, F>(mut self, version: u32, upgrade: F) -> Self
+    where
+        Old: DeserializeOwned,
+        F: Fn(Old) -> T + 'static,
+    {
+        let upgrade = move |payload: &[u8], size_limit: Option<Bounded>| {
+            let old = Bincode::deserialise::<Old>(payload, size_limit)?;
+            Ok(upgrade(old))
+        };
+        let _ = self.upgrades.insert(version, Box::new(upgrade));
+        self
+    }
+}
+
+/// Serialise a `Versioned` type inside a versioned envelope with no limit on the size of the
+/// serialised data.
+pub fn serialise_versioned<T: Versioned>(data: &T) -> Result<Vec<u8>, SerialisationError> {
+    Ok(with_header(T::VERSION, &Bincode::serialise(data, None)?))
+}
+
+/// Serialise a `Versioned` type inside a versioned envelope with max limit specified.
+///
+/// The limit applies to the payload and excludes the envelope header.
+pub fn serialise_versioned_with_limit<T: Versioned>(
+    data: &T,
+    size_limit: Bounded,
+) -> Result<Vec<u8>, SerialisationError> {
+    Ok(with_header(
+        T::VERSION,
+        &Bincode::serialise(data, Some(size_limit))?,
+    ))
+}
+
+/// Deserialise a `Versioned` type from a versioned envelope with the process-wide [default
+/// limits](fn.set_default_limits.html), upgrading it if it was written with an older schema
+/// version.
+pub fn deserialise_versioned<T: Versioned + 'static>(data: &[u8]) -> Result<T, SerialisationError> {
+    deserialise_versioned_impl(data, None)
+}
+
+/// Deserialise a `Versioned` type from a versioned envelope with max size limit specified,
+/// upgrading it if it was written with an older schema version.
+///
+/// The limit applies to the payload and excludes the envelope header.
+pub fn deserialise_versioned_with_limit<T: Versioned + 'static>(
+    data: &[u8],
+    size_limit: Bounded,
+) -> Result<T, SerialisationError> {
+    deserialise_versioned_impl(data, Some(size_limit))
+}
+
+/// Returns the schema version recorded in the header of a versioned envelope.
+pub fn versioned_schema(data: &[u8]) -> Result<u32, SerialisationError> {
+    split_header(data).map(|(version, _)| version)
+}
+
+fn deserialise_versioned_impl<T: Versioned + 'static>(
+    data: &[u8],
+    size_limit: Option<Bounded>,
+) -> Result<T, SerialisationError> {
+    let (version, payload) = split_header(data)?;
+    if version == T::VERSION {
+        return Bincode::deserialise(payload, size_limit);
+    }
+
+    let migrations = T::migrations(Migrations::new());
+    match migrations.upgrades.get(&version) {
+        Some(upgrade) => upgrade(payload, size_limit),
+        None => Err(SerialisationError::UnsupportedVersion(version, T::VERSION)),
+    }
+}
+
+fn with_header(version: u32, payload: &[u8]) -> Vec<u8> {
+    let mut envelope = Vec::with_capacity(HEADER_LEN + payload.len());
+    envelope.extend_from_slice(&VERSIONED_MAGIC);
+    envelope.extend_from_slice(&version.to_le_bytes());
+    envelope.extend_from_slice(payload);
+    envelope
+}
+
+fn split_header(data: &[u8]) -> Result<(u32, &[u8]), SerialisationError> {
+    if data.len() < HEADER_LEN || data[..4] != VERSIONED_MAGIC {
+        return Err(SerialisationError::MissingVersionHeader);
+    }
+    let mut version = [0; 4];
+    version.copy_from_slice(&data[4..HEADER_LEN]);
+    Ok((u32::from_le_bytes(version), &data[HEADER_LEN..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serialisation::serialise;
+    use bincode::ErrorKind;
+    use serde_derive::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct PeerV1 {
+        name: String,
+    }
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct PeerV2 {
+        name: String,
+        port: u16,
+    }
+
+    impl Versioned for PeerV1 {
+        const VERSION: u32 = 1;
+    }
+
+    impl Versioned for PeerV2 {
+        const VERSION: u32 = 2;
+
+        fn migrations(migrations: Migrations<Self>) -> Migrations<Self> {
+            migrations.register(1, |old: PeerV1| PeerV2 {
+                name: old.name,
+                port: 5483,
+            })
+        }
+    }
+
+    #[test]
+    fn versioned_round_trip_and_upgrade() {
+        let current = PeerV2 {
+            name: "node".to_owned(),
+            port: 1,
+        };
+        let serialised = unwrap!(serialise_versioned(&current));
+        assert_eq!(serialised[..4], VERSIONED_MAGIC);
+        assert_eq!(unwrap!(versioned_schema(&serialised)), 2);
+        assert_eq!(current, unwrap!(deserialise_versioned(&serialised)));
+
+        // Data written with the old schema is upgraded.
+        let old = unwrap!(serialise_versioned(&PeerV1 {
+            name: "node".to_owned(),
+        }));
+        let upgraded: PeerV2 = unwrap!(deserialise_versioned(&old));
+        assert_eq!(
+            upgraded,
+            PeerV2 {
+                name: "node".to_owned(),
+                port: 5483,
+            }
+        );
+
+        // The old type has no knowledge of the newer schema.
+        match unwrap_err!(deserialise_versioned::<PeerV1>(&serialised)) {
+            SerialisationError::UnsupportedVersion(2, 1) => (),
+            err => panic!("{:?}", err),
+        }
+
+        // Unversioned data is rejected.
+        let raw = unwrap!(serialise(&current));
+        match unwrap_err!(deserialise_versioned::<PeerV2>(&raw)) {
+            SerialisationError::MissingVersionHeader => (),
+            err => panic!("{:?}", err),
+        }
+    }
+
+    #[test]
+    fn versioned_limits() {
+        let upper_limit = Bounded(16);
+        let peer = PeerV1 {
+            name: "a very long node name".to_owned(),
+        };
+        match unwrap_err!(serialise_versioned_with_limit(&peer, upper_limit)) {
+            SerialisationError::Serialise(ErrorKind::SizeLimit) => (),
+            err => panic!("{:?}", err),
+        }
+
+        // The limit is applied to both current and upgraded payloads.
+        let serialised = unwrap!(serialise_versioned(&peer));
+        match unwrap_err!(deserialise_versioned_with_limit::<PeerV1>(
+            &serialised,
+            upper_limit
+        )) {
+            SerialisationError::Deserialise(ErrorKind::SizeLimit, _) => (),
+            err => panic!("{:?}", err),
+        }
+        match unwrap_err!(deserialise_versioned_with_limit::<PeerV2>(
+            &serialised,
+            upper_limit
+        )) {
+            SerialisationError::Deserialise(ErrorKind::SizeLimit, _) => (),
+            err => panic!("{:?}", err),
+        }
+    }
+}
@@ -0,0 +1,135 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// https://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+#![doc(
+    html_logo_url = "https://raw.githubusercontent.com/maidsafe/QA/master/Images/maidsafe_logo.png",
+    html_favicon_url = "https://maidsafe.net/img/favicon.ico",
+    test(attr(forbid(warnings)))
+)]
+// For explanation of lint checks, run `rustc -W help` or see
+// https://github.com/maidsafe/QA/blob/master/Documentation/Rust%20Lint%20Checks.md
+#![forbid(
+    arithmetic_overflow,
+    mutable_transmutes,
+    no_mangle_const_items,
+    unknown_crate_types,
+    warnings
+)]
+#![deny(
+    bad_style,
+    deprecated,
+    improper_ctypes,
+    missing_docs,
+    non_shorthand_field_patterns,
+    overflowing_literals,
+    stable_features,
+    unconditional_recursion,
+    unknown_lints,
+    unsafe_code,
+    unused,
+    unused_allocation,
+    unused_attributes,
+    unused_comparisons,
+    unused_features,
+    unused_parens,
+    while_true
+)]
+#![warn(
+    trivial_casts,
+    trivial_numeric_casts,
+    unused_extern_crates,
+    unused_import_braces,
+    unused_qualifications,
+    unused_results
+)]
+#![allow(
+    box_pointers,
+    missing_copy_implementations,
+    missing_debug_implementations,
+    variant_size_differences
+)]
+
+#[macro_use]
+extern crate unwrap;
+
+use bincode::ErrorKind;
+use maidsafe_utilities::serialisation::{
+    default_limits, deserialise, deserialise_as, deserialise_from, deserialise_unbounded,
+    serialise, serialise_as, set_default_limits, Bincode, DeserialiseLimits, Format,
+    SerialisationError,
+};
+
+// The default limits are process-wide, so are changed in their own test binary.
+// The collection length limit applies even to formats which don't give the length up front.
+fn check_collection_len<F: Format>() {
+    let serialised = unwrap!(serialise_as::<F, _>(&vec![0u8; 3]));
+    match unwrap_err!(deserialise_as::<F, Vec<u8>>(&serialised)) {
+        SerialisationError::CollectionTooLong(len, 2, _) => assert!(len > 2),
+        err => panic!("{:?}", err),
+    }
+}
+
+#[test]
+fn set_deserialise_limits() {
+    assert_eq!(default_limits(), DeserialiseLimits::default());
+
+    // Byte vectors of several MiB are within the defaults.
+    let blob = vec![7u8; 2 << 20];
+    assert_eq!(
+        blob,
+        unwrap!(deserialise::<Vec<u8>>(&unwrap!(serialise(&blob))))
+    );
+
+    let data = vec![1u32, 2, 3];
+    let serialised = unwrap!(serialise(&data));
+
+    set_default_limits(DeserialiseLimits {
+        max_bytes: 8,
+        ..Default::default()
+    });
+    match unwrap_err!(deserialise::<Vec<u32>>(&serialised)) {
+        SerialisationError::Deserialise(ErrorKind::SizeLimit, _) => (),
+        err => panic!("{:?}", err),
+    }
+    match unwrap_err!(deserialise_from::<_, Vec<u32>>(&mut &serialised[..])) {
+        SerialisationError::Deserialise(ErrorKind::SizeLimit, _) => (),
+        err => panic!("{:?}", err),
+    }
+
+    set_default_limits(DeserialiseLimits {
+        max_collection_len: 2,
+        ..Default::default()
+    });
+    match unwrap_err!(deserialise::<Vec<u32>>(&serialised)) {
+        SerialisationError::CollectionTooLong(3, 2, _) => (),
+        err => panic!("{:?}", err),
+    }
+    check_collection_len::<Bincode>();
+    #[cfg(feature = "json")]
+    check_collection_len::<maidsafe_utilities::serialisation::Json>();
+    #[cfg(feature = "msgpack")]
+    check_collection_len::<maidsafe_utilities::serialisation::MessagePack>();
+    #[cfg(feature = "cbor")]
+    check_collection_len::<maidsafe_utilities::serialisation::Cbor>();
+
+    set_default_limits(DeserialiseLimits {
+        max_depth: 1,
+        ..Default::default()
+    });
+    match unwrap_err!(deserialise::<Vec<u32>>(&serialised)) {
+        SerialisationError::NestingTooDeep(1, _) => (),
+        err => panic!("{:?}", err),
+    }
+    assert_eq!(5, unwrap!(deserialise::<u32>(&unwrap!(serialise(&5u32)))));
+
+    assert_eq!(
+        data,
+        unwrap!(deserialise_unbounded::<Vec<u32>>(&serialised))
+    );
+}
@@ -0,0 +1,135 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// https://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+#![doc(
+    html_logo_url = "https://raw.githubusercontent.com/maidsafe/QA/master/Images/maidsafe_logo.png",
+    html_favicon_url = "https://maidsafe.net/img/favicon.ico",
+    test(attr(forbid(warnings)))
+)]
+// For explanation of lint checks, run `rustc -W help` or see
+// https://github.com/maidsafe/QA/blob/master/Documentation/Rust%20Lint%20Checks.md
+#![forbid(
+    arithmetic_overflow,
+    mutable_transmutes,
+    no_mangle_const_items,
+    unknown_crate_types,
+    warnings
+)]
+#![deny(
+    bad_style,
+    deprecated,
+    improper_ctypes,
+    missing_docs,
+    non_shorthand_field_patterns,
+    overflowing_literals,
+    stable_features,
+    unconditional_recursion,
+    unknown_lints,
+    unsafe_code,
+    unused,
+    unused_allocation,
+    unused_attributes,
+    unused_comparisons,
+    unused_features,
+    unused_parens,
+    while_true
+)]
+#![warn(
+    trivial_casts,
+    trivial_numeric_casts,
+    unused_extern_crates,
+    unused_import_braces,
+    unused_qualifications,
+    unused_results
+)]
+#![allow(
+    box_pointers,
+    missing_copy_implementations,
+    missing_debug_implementations,
+    variant_size_differences
+)]
+
+#[macro_use]
+extern crate log as logger;
+#[macro_use]
+extern crate unwrap;
+
+use logger::LogLevelFilter;
+use maidsafe_utilities::log;
+use std::fs;
+use std::{env, process};
+
+mod routing {
+    pub mod core {
+        pub fn log_messages(id: usize) {
+            debug!("routing::core debug message {}", id);
+            trace!("routing::core trace message {}", id);
+        }
+    }
+}
+
+fn log_messages(id: usize) {
+    info!("Root info message {}", id);
+    routing::core::log_messages(id);
+}
+
+#[test]
+fn log_handle() {
+    assert!(log::handle().is_none());
+
+    let path = env::temp_dir().join(format!(
+        "maidsafe_utilities_log_handle_{}.log",
+        process::id()
+    ));
+    unwrap!(log::init_to_file(false, &path, false));
+    let handle = unwrap!(log::handle());
+
+    log_messages(0);
+
+    handle.set_module_level("log_handle::routing::core", LogLevelFilter::Debug);
+    log_messages(1);
+
+    unwrap!(handle.set_levels("info,log_handle::routing::core=trace"));
+    log_messages(2);
+    assert!(handle.set_levels("routing=loud").is_err());
+
+    handle.reset();
+    log_messages(3);
+
+    handle.set_default_level(LogLevelFilter::Trace);
+    log_messages(4);
+
+    log::flush();
+    let contents = unwrap!(fs::read_to_string(&path));
+    let found = |message: &str| contents.contains(message);
+
+    assert!(!found("Root info message 0"));
+    assert!(!found("debug message 0"));
+
+    assert!(!found("Root info message 1"));
+    assert!(found("debug message 1"));
+    assert!(!found("trace message 1"));
+
+    assert!(found("Root info message 2"));
+    assert!(found("trace message 2"));
+
+    assert!(!found("Root info message 3"));
+    assert!(!found("debug message 3"));
+
+    assert!(found("Root info message 4"));
+    assert!(found("trace message 4"));
+
+    // Nothing is written once the appenders have been shut down.
+    log::shutdown();
+    error!("Message after shutdown");
+    log::flush();
+    assert!(!unwrap!(fs::read_to_string(&path)).contains("after shutdown"));
+
+    let _ = fs::remove_file(&path);
+}
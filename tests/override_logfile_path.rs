@@ -64,8 +64,6 @@ use maidsafe_utilities::log;
 use std::env;
 use std::fs::{self, File};
 use std::io::Read;
-use std::thread;
-use std::time::Duration;
 
 #[test]
 fn override_logfile_path() {
@@ -87,8 +85,7 @@ fn override_logfile_path() {
 
     error!("SECRET-MESSAGE");
 
-    // Wait for async file writer
-    thread::sleep(Duration::from_millis(500));
+    log::flush();
 
     let mut log_file_path = unwrap!(config_file_handler::current_bin_dir());
     log_file_path.push(LOG_FILE);
@@ -0,0 +1,104 @@
+// Copyright 2018 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under the MIT license <LICENSE-MIT
+// https://opensource.org/licenses/MIT> or the Modified BSD license <LICENSE-BSD
+// https://opensource.org/licenses/BSD-3-Clause>, at your option. This file may not be copied,
+// modified, or distributed except according to those terms. Please review the Licences for the
+// specific language governing permissions and limitations relating to use of the SAFE Network
+// Software.
+
+#![doc(
+    html_logo_url = "https://raw.githubusercontent.com/maidsafe/QA/master/Images/maidsafe_logo.png",
+    html_favicon_url = "https://maidsafe.net/img/favicon.ico",
+    test(attr(forbid(warnings)))
+)]
+// For explanation of lint checks, run `rustc -W help` or see
+// https://github.com/maidsafe/QA/blob/master/Documentation/Rust%20Lint%20Checks.md
+#![forbid(
+    arithmetic_overflow,
+    mutable_transmutes,
+    no_mangle_const_items,
+    unknown_crate_types,
+    warnings
+)]
+#![deny(
+    bad_style,
+    deprecated,
+    improper_ctypes,
+    missing_docs,
+    non_shorthand_field_patterns,
+    overflowing_literals,
+    stable_features,
+    unconditional_recursion,
+    unknown_lints,
+    unsafe_code,
+    unused,
+    unused_allocation,
+    unused_attributes,
+    unused_comparisons,
+    unused_features,
+    unused_parens,
+    while_true
+)]
+#![warn(
+    trivial_casts,
+    trivial_numeric_casts,
+    unused_extern_crates,
+    unused_import_braces,
+    unused_qualifications,
+    unused_results
+)]
+#![allow(
+    box_pointers,
+    missing_copy_implementations,
+    missing_debug_implementations,
+    variant_size_differences
+)]
+
+#[macro_use]
+extern crate log as logger;
+#[macro_use]
+extern crate unwrap;
+
+use logger::LogLevelFilter;
+use maidsafe_utilities::log;
+use std::fs;
+use std::{env, process};
+
+#[test]
+fn reinit_logging() {
+    let first = env::temp_dir().join(format!("maidsafe_utilities_reinit_{}_1.log", process::id()));
+    let second = env::temp_dir().join(format!("maidsafe_utilities_reinit_{}_2.log", process::id()));
+
+    unwrap!(log::reinit_to_file(false, &first, false));
+    assert!(log::init(false).is_err());
+    unwrap!(log::handle()).set_default_level(LogLevelFilter::Info);
+    info!("First message");
+
+    // Replacing the config flushes and closes the old appenders, and discards levels set through
+    // the handle.
+    unwrap!(log::reinit_to_file(false, &second, false));
+    info!("Dropped message");
+    warn!("Second message");
+    log::flush();
+
+    let first_contents = unwrap!(fs::read_to_string(&first));
+    let second_contents = unwrap!(fs::read_to_string(&second));
+    assert!(first_contents.contains("First message"));
+    assert!(!first_contents.contains("Second message"));
+    assert!(second_contents.contains("Second message"));
+    assert!(!second_contents.contains("First message"));
+    assert!(!second_contents.contains("Dropped message"));
+
+    // Reinitialising with the same path truncates the file.
+    unwrap!(log::reinit_to_file(false, &second, false));
+    error!("Third message");
+    log::flush();
+    let second_contents = unwrap!(fs::read_to_string(&second));
+    assert!(!second_contents.contains("Second message"));
+    assert!(second_contents.contains("Third message"));
+
+    log::shutdown();
+    let _ = fs::remove_file(&first);
+    let _ = fs::remove_file(&second);
+}